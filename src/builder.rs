@@ -0,0 +1,91 @@
+use crate::{
+    grep::{Match, grep_with_sink},
+    grep_error::GrepResult,
+    options::{Algo, Mode, Options},
+};
+
+/// Search configured in code instead of command line
+#[derive(Debug, Clone)]
+pub struct SearchBuilder {
+    options: Options,
+}
+
+impl SearchBuilder {
+    pub fn new<I, S>(pattern: &str, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        SearchBuilder {
+            options: Options::new(pattern, paths),
+        }
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+
+    pub fn algo(mut self, algo: Algo) -> Self {
+        self.options.algo = algo;
+        self
+    }
+
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        self.options.max_errors = Some(max_errors);
+        self
+    }
+
+    pub fn ignore_case(mut self, ignore_case: bool) -> Self {
+        self.options.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn invert_match(mut self, invert_match: bool) -> Self {
+        self.options.invert_match = invert_match;
+        self
+    }
+
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.options.multiline = multiline;
+        self
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.options.walk.recursive = recursive;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.options.threads = threads;
+        self
+    }
+
+    /// Any other option
+    pub fn configure<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Options),
+    {
+        f(&mut self.options);
+        self
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Pass every found line to `sink`, `Printer` shows them as the binary does.
+    /// Errors of single files don't stop the search, the first one is returned
+    pub fn search<F>(&self, sink: F) -> GrepResult<()>
+    where
+        F: FnMut(Match),
+    {
+        grep_with_sink(&self.options, sink, |_| {})
+    }
+
+    pub fn matches(&self) -> GrepResult<Vec<Match>> {
+        let mut result = Vec::new();
+        self.search(|found| result.push(found))?;
+        Ok(result)
+    }
+}
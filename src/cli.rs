@@ -0,0 +1,333 @@
+use clap::Parser;
+
+use grep_rs::{
+    Algo, ColorMode, EditOptions, Edited, Encoding, ErrorType, GrepResult, Match, MaxColumns, Mode,
+    Options, PrintOptions, Printer, WalkOptions, WindowUnit, edit_with_sink, grep_with_sink,
+    style::{Palette, Style},
+};
+
+#[derive(Parser, Debug, Clone)]
+pub struct Args {
+    /// String that need to find
+    pub substring: String,
+
+    /// File names
+    #[arg(required = true)]
+    pub files: Vec<String>,
+
+    /// Search from left side or right
+    #[arg(short, long, value_enum, default_value_t = Mode::All, ignore_case = true)]
+    pub mode: Mode,
+
+    #[arg(short, long, value_enum, default_value_t = Algo::Kmp, ignore_case = true)]
+    pub algo: Algo,
+
+    /// Maximum edit distance of found part for fuzzy algorithm, 1 by default
+    #[arg(long)]
+    pub max_errors: Option<usize>,
+
+    /// Ignore case
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Select lines without match of the mode
+    #[arg(short = 'v', long)]
+    pub invert_match: bool,
+
+    /// Allow matches across lines. '\n' in substring means line break
+    #[arg(short = 'U', long)]
+    pub multiline: bool,
+
+    /// Records are separated by NUL instead of line break
+    #[arg(short = 'z', long, conflicts_with = "record_separator")]
+    pub null_data: bool,
+
+    /// Char separating records instead of line break, e.g. ';', '\0' or '\x1e'
+    #[arg(long, value_name = "SEP", value_parser = parse_separator)]
+    pub record_separator: Option<char>,
+
+    /// Search and print records of lines separated by blank lines
+    #[arg(short, long)]
+    pub paragraph: bool,
+
+    /// Start a new record of lines at every line beginning with a match of this pattern,
+    /// e.g. a timestamp. It's found by the selected algorithm, so with kmp it's a literal prefix
+    #[arg(long, value_name = "PATTERN")]
+    pub record_start: Option<String>,
+
+    /// Encoding of file. Default = Auto (BOM sniffing, otherwise UTF-8)
+    #[arg(long, value_enum, default_value_t = Encoding::Auto, ignore_case = true)]
+    pub encoding: Encoding,
+
+    /// Number of threads for searching files. 0 = number of available cores
+    #[arg(short = 'j', long, default_value = "1")]
+    pub threads: usize,
+
+    /// Print results of files as soon as they are completed, instead of in the given order
+    #[arg(long)]
+    pub as_completed: bool,
+
+    /// Map regular files into memory instead of reading them
+    #[arg(long)]
+    pub mmap: bool,
+
+    /// Write replacements into files. Non empty SUFFIX keeps backup of original file
+    #[arg(long, value_name = "SUFFIX", num_args = 0..=1, require_equals = true, default_missing_value = "", requires = "replace")]
+    pub in_place: Option<String>,
+
+    /// Show summary of in-place editing without writing files
+    #[arg(long, requires = "in_place")]
+    pub dry_run: bool,
+
+    /// Print nothing, exit with zero status at the first match
+    #[arg(short, long, visible_alias = "silent")]
+    pub quiet: bool,
+
+    /// Don't report nonexistent and unreadable files
+    #[arg(short = 's', long)]
+    pub no_messages: bool,
+
+    #[command(flatten)]
+    pub walk_config: WalkConfig,
+
+    #[command(flatten)]
+    pub show_config: ShowConfig,
+}
+
+impl Args {
+    pub fn record_separator(&self) -> char {
+        match (self.null_data, self.record_separator) {
+            (true, _) => '\0',
+            (false, Some(sep)) => sep,
+            (false, None) => '\n',
+        }
+    }
+
+    fn options(&self) -> Options {
+        let mut options = Options::new(&self.substring, &self.files);
+        options.mode = self.mode;
+        options.algo = self.algo;
+        options.max_errors = self.max_errors;
+        options.ignore_case = self.ignore_case;
+        options.invert_match = self.invert_match;
+        options.multiline = self.multiline;
+        options.record_separator = self.record_separator();
+        options.paragraph = self.paragraph;
+        options.record_start = self.record_start.clone();
+        options.encoding = self.encoding;
+        options.threads = self.threads;
+        options.as_completed = self.as_completed;
+        options.mmap = self.mmap;
+        options.quiet = self.quiet;
+        options.walk = self.walk_config.options();
+        options
+    }
+}
+
+impl WalkConfig {
+    fn options(&self) -> WalkOptions {
+        let mut options = WalkOptions::default();
+        options.recursive = self.recursive;
+        options.no_ignore = self.no_ignore;
+        options.hidden = self.hidden;
+        options.max_depth = self.max_depth;
+        options.follow = self.follow;
+        options.one_file_system = self.one_file_system;
+        options.globs = self.globs.clone();
+        options.types = self.types.clone();
+        options.type_add = self.type_add.clone();
+        options
+    }
+}
+
+impl ShowConfig {
+    fn options(&self) -> PrintOptions {
+        let mut options = PrintOptions::default();
+        options.number = self.number;
+        options.column = self.column;
+        options.byte_offset = self.byte_offset;
+        options.vimgrep = self.vimgrep;
+        options.color = self.color.clone();
+        options.colors = self.colors.clone();
+        options.color_mode = self.color_mode;
+        options.window_size = self.window_size;
+        options.window_unit = self.window_unit;
+        options.max_columns = self.max_columns;
+        options.max_columns_preview = self.max_columns_preview;
+        options.replace = self.replace.clone();
+        options
+    }
+}
+
+/// Search or edit files as the command line asks and pass every printed line to `sink`
+/// and every error of a file to `report`
+pub fn run<F, E>(args: &Args, mut sink: F, report: E) -> GrepResult<()>
+where
+    F: FnMut(String),
+    E: FnMut(&ErrorType),
+{
+    let options = args.options();
+
+    if let Some(suffix) = &args.in_place {
+        let mut edit = EditOptions::new(args.show_config.replace.as_deref().unwrap_or_default());
+        edit.backup_suffix = suffix.clone();
+        edit.dry_run = args.dry_run;
+
+        let dry_run = if args.dry_run { " (dry run)" } else { "" };
+        let summary = |Edited { path, replacements }: Edited| {
+            sink(format!("{path}: {replacements} replacements{dry_run}"))
+        };
+        return edit_with_sink(&options, &edit, summary, report);
+    }
+
+    let printer = Printer::new(args.show_config.options(), &options);
+    let print = |found: Match| printer.lines(&found).into_iter().for_each(&mut sink);
+    grep_with_sink(&options, print, report)
+}
+
+/// Single char or one of escapes: \0, \n, \r, \t, \xHH
+fn parse_separator(s: &str) -> Result<char, String> {
+    let sep = match s {
+        "\\0" => Some('\0'),
+        "\\n" => Some('\n'),
+        "\\r" => Some('\r'),
+        "\\t" => Some('\t'),
+        _ => match s.strip_prefix("\\x") {
+            Some(hex) if hex.len() == 2 => u8::from_str_radix(hex, 16).ok().map(char::from),
+            Some(_) => None,
+            None => {
+                let mut chars = s.chars();
+                chars.next().filter(|_| chars.next().is_none())
+            }
+        },
+    };
+
+    sep.ok_or_else(|| format!("'{s}' is not a single char or escape"))
+}
+
+fn parse_max_columns(s: &str) -> Result<MaxColumns, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(MaxColumns::Auto);
+    }
+
+    s.parse()
+        .map(MaxColumns::Fixed)
+        .map_err(|_| format!("'{s}' is not a number or 'auto'"))
+}
+
+/*impl Args {
+    pub fn new_with_default(substring: String, file: String) -> Self {
+        Args {
+            substring,
+            file,
+            mode: Mode::All,
+            algo: Algo::Kmp,
+            ignore_case: true,
+            show_config: ShowConfig::default(),
+        }
+    }
+}*/
+
+#[derive(Parser, Debug, Clone)]
+pub struct ShowConfig {
+    /// Show number of line
+    #[arg(short, long)]
+    pub number: bool,
+
+    /// Show 1-based column of the first shown match in line
+    #[arg(long)]
+    pub column: bool,
+
+    /// Show byte offset of the first shown match in the decoded file
+    #[arg(short, long)]
+    pub byte_offset: bool,
+
+    /// Show every match as 'path:line:column:text', e.g. for quickfix list of Vim
+    #[arg(long)]
+    pub vimgrep: bool,
+
+    /// Style of selected part: color (name, 0-255, #rrggbb), bg=COLOR, bold, underline,
+    /// reverse, separated by ','. Default = red, or 'ms' of GREP_RS_COLORS and GREP_COLORS
+    #[arg(short, long = "match-color", value_name = "STYLE", value_parser = Style::parse)]
+    pub color: Option<Style>,
+
+    /// Styles of output parts as 'KEY=STYLE' separated by ':'. Keys: fn (path), ln (line number),
+    /// cn (column), bn (byte offset), se (separator), ms (match), sl (selected line)
+    #[arg(long, value_name = "SPEC", value_parser = Palette::parse)]
+    pub colors: Option<Palette>,
+
+    /// When to use colors. Auto = only for terminal, respecting NO_COLOR and CLICOLOR_FORCE
+    #[arg(long = "color", value_name = "WHEN", value_enum, default_value_t = ColorMode::Auto, ignore_case = true)]
+    pub color_mode: ColorMode,
+
+    /// Size of window around each match in string, trimmed parts are marked with '…'. Default value = 10
+    #[arg(short, long, default_value = "10", ignore_case = true)]
+    pub window_size: usize,
+
+    /// Unit of window size. Windows never split a grapheme
+    #[arg(long, value_name = "UNIT", value_enum, default_value_t = WindowUnit::Bytes, ignore_case = true)]
+    pub window_unit: WindowUnit,
+
+    /// Omit lines wider than NUM columns, 'auto' = width of terminal
+    #[arg(short = 'M', long, value_name = "NUM", value_parser = parse_max_columns)]
+    pub max_columns: Option<MaxColumns>,
+
+    /// Show long lines trimmed around the first match instead of omitting them
+    #[arg(long, requires = "max_columns")]
+    pub max_columns_preview: bool,
+
+    /// Show matched part replaced with this string. Files are not modified
+    #[arg(long, value_name = "REPLACEMENT")]
+    pub replace: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct WalkConfig {
+    /// Search files in directories recursively
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Don't respect .gitignore, .git/info/exclude, global git excludes and .grepignore
+    #[arg(long)]
+    pub no_ignore: bool,
+
+    /// Search hidden files and directories
+    #[arg(long)]
+    pub hidden: bool,
+
+    /// Descend at most this number of directories below given paths
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Follow symbolic links
+    #[arg(short = 'L', long)]
+    pub follow: bool,
+
+    /// Don't descend into directories on other file systems
+    #[arg(long)]
+    pub one_file_system: bool,
+
+    /// Search only files matching glob. Glob starting with '!' excludes files
+    #[arg(short, long = "glob", value_name = "GLOB")]
+    pub globs: Vec<String>,
+
+    /// Search only files of type, e.g. rust, py, js
+    #[arg(short, long = "type", value_name = "TYPE")]
+    pub types: Vec<String>,
+
+    /// Add file type in format 'name:glob', e.g. 'web:*.{html,css}'
+    #[arg(long, value_name = "TYPE_SPEC")]
+    pub type_add: Vec<String>,
+}
+
+/*
+/// For testing
+impl Default for ShowConfig {
+    fn default() -> Self {
+        ShowConfig {
+            number: false,
+            color: Color::Red,
+            window_size: 10,
+        }
+    }
+}*/
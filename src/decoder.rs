@@ -0,0 +1,191 @@
+use std::borrow::Cow;
+
+use encoding_rs::{CoderResult, KOI8_R, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1251};
+
+use crate::options::Encoding;
+
+/// Transcode raw file content to UTF-8, so the searcher always works with `&str`.
+/// Offsets of matches are relative to the decoded text. Valid UTF-8 is borrowed.
+pub fn decode(bytes: &[u8], encoding: Encoding) -> Cow<'_, str> {
+    match detect(bytes, encoding) {
+        Some((encoding, bom_len)) => {
+            let (text, _) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+            text
+        }
+        None => Cow::Owned(bytes.iter().map(|&b| b as char).collect()),
+    }
+}
+
+/// Decoder of content read in chunks, the result is the same as of `decode`
+/// for the whole content
+pub struct StreamDecoder {
+    /// Latin-1 is not in encoding_rs, so it's nothing
+    inner: Option<encoding_rs::Decoder>,
+}
+
+impl StreamDecoder {
+    pub fn new(encoding: Encoding) -> Self {
+        let inner = match encoding {
+            // BOM sniffing with fallback to UTF-8
+            Encoding::Auto => Some(UTF_8.new_decoder()),
+            Encoding::Latin1 => None,
+            // Only BOM of the given encoding is removed
+            Encoding::Utf8 => Some(UTF_8.new_decoder_with_bom_removal()),
+            Encoding::Utf16le => Some(UTF_16LE.new_decoder_with_bom_removal()),
+            Encoding::Utf16be => Some(UTF_16BE.new_decoder_with_bom_removal()),
+            Encoding::Windows1251 => Some(WINDOWS_1251.new_decoder_with_bom_removal()),
+            Encoding::Koi8r => Some(KOI8_R.new_decoder_with_bom_removal()),
+        };
+
+        StreamDecoder { inner }
+    }
+
+    /// Append decoded `bytes` to `text`. Incomplete sequence at the end of a chunk
+    /// is kept until the next one, `last` chunk decodes it as U+FFFD.
+    pub fn decode(&mut self, mut bytes: &[u8], last: bool, text: &mut String) {
+        let Some(decoder) = &mut self.inner else {
+            text.extend(bytes.iter().map(|&b| b as char));
+            return;
+        };
+
+        loop {
+            let needed = decoder.max_utf8_buffer_length(bytes.len());
+            text.reserve(needed.unwrap_or(bytes.len()));
+
+            let (result, read, _) = decoder.decode_to_string(bytes, text, last);
+            bytes = &bytes[read..];
+
+            if result == CoderResult::InputEmpty {
+                return;
+            }
+        }
+    }
+}
+
+/// Transcode `text` back into encoding of `original`, which it was decoded from,
+/// with the same BOM. Returns nothing if some char can't be represented.
+pub fn encode(text: &str, original: &[u8], encoding: Encoding) -> Option<Vec<u8>> {
+    let Some((encoding, bom_len)) = detect(original, encoding) else {
+        return text.chars().map(|c| u8::try_from(c).ok()).collect();
+    };
+
+    let mut result = original[..bom_len].to_vec();
+
+    // encoding_rs encodes UTF-16 text as UTF-8, like browsers do
+    if encoding == UTF_16LE {
+        result.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    } else if encoding == UTF_16BE {
+        result.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    } else {
+        let (bytes, _, unmappable) = encoding.encode(text);
+        if unmappable {
+            return None;
+        }
+        result.extend_from_slice(&bytes);
+    }
+
+    Some(result)
+}
+
+/// Encoding of `bytes` and length of its BOM. Auto sniffs BOM for UTF-8/UTF-16LE/UTF-16BE
+/// and falls back to UTF-8. Latin-1 is not in encoding_rs, so it's nothing.
+fn detect(bytes: &[u8], encoding: Encoding) -> Option<(&'static encoding_rs::Encoding, usize)> {
+    let encoding = match encoding {
+        Encoding::Auto => {
+            return Some(encoding_rs::Encoding::for_bom(bytes).unwrap_or((UTF_8, 0)));
+        }
+        Encoding::Utf8 => UTF_8,
+        Encoding::Utf16le => UTF_16LE,
+        Encoding::Utf16be => UTF_16BE,
+        Encoding::Latin1 => return None,
+        Encoding::Windows1251 => WINDOWS_1251,
+        Encoding::Koi8r => KOI8_R,
+    };
+
+    // Only BOM of the given encoding is removed
+    let bom_len = match encoding_rs::Encoding::for_bom(bytes) {
+        Some((bom_encoding, len)) if bom_encoding == encoding => len,
+        _ => 0,
+    };
+
+    Some((encoding, bom_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_bom() {
+        let result = decode(b"\xEF\xBB\xBFaba", Encoding::Auto);
+        assert_eq!(result, "aba");
+    }
+
+    #[test]
+    fn utf16le_bom() {
+        let result = decode(b"\xFF\xFEa\0b\0a\0", Encoding::Auto);
+        assert_eq!(result, "aba");
+    }
+
+    #[test]
+    fn utf16be_bom() {
+        let result = decode(b"\xFE\xFF\0a\0b\0a", Encoding::Auto);
+        assert_eq!(result, "aba");
+    }
+
+    #[test]
+    fn latin1() {
+        let result = decode(b"caf\xE9", Encoding::Latin1);
+        assert_eq!(result, "café");
+    }
+
+    #[test]
+    fn windows1251() {
+        let result = decode(b"\xEF\xF0\xE8\xE2\xE5\xF2", Encoding::Windows1251);
+        assert_eq!(result, "привет");
+    }
+
+    #[test]
+    fn koi8r() {
+        let result = decode(b"\xD0\xD2\xC9\xD7\xC5\xD4", Encoding::Koi8r);
+        assert_eq!(result, "привет");
+    }
+
+    #[test]
+    fn stream_same_as_whole() {
+        let cases: [(&[u8], Encoding); 4] = [
+            (b"\xFF\xFEa\0\n\0\x3F\x04b\0", Encoding::Auto),
+            (b"\xEF\xBB\xBF\xD0\xBF\xff\n", Encoding::Auto),
+            (b"\xEF\xF0\xE8\n", Encoding::Windows1251),
+            (b"caf\xE9\n", Encoding::Latin1),
+        ];
+
+        // Chunks of one byte split every BOM and multibyte sequence
+        for (bytes, encoding) in cases {
+            let mut decoder = StreamDecoder::new(encoding);
+            let mut text = String::new();
+            for byte in bytes.chunks(1) {
+                decoder.decode(byte, false, &mut text);
+            }
+            decoder.decode(&[], true, &mut text);
+
+            assert_eq!(text, decode(bytes, encoding));
+        }
+    }
+
+    #[test]
+    fn encode_back() {
+        let bytes = b"\xFF\xFEa\0b\0a\0";
+        assert_eq!(
+            encode("aXa", bytes, Encoding::Auto).unwrap(),
+            b"\xFF\xFEa\0X\0a\0"
+        );
+
+        let bytes = b"\xEF\xF0\xE8";
+        assert_eq!(encode("при", bytes, Encoding::Windows1251).unwrap(), bytes);
+
+        assert_eq!(encode("café", b"", Encoding::Latin1).unwrap(), b"caf\xE9");
+        assert!(encode("привет", b"", Encoding::Latin1).is_none());
+        assert!(encode("café", b"", Encoding::Koi8r).is_none());
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{
+    decoder,
+    grep::{self, Edited, Found},
+    grep_error::{ErrorType, GrepResult},
+    options::{EditOptions, Mode, Options},
+    searcher::{Searcher, non_overlapping},
+};
+
+/// Replace matched parts in `file` and write it back atomically in its encoding.
+/// Matches are found by the same steps as in search. Returns changed file,
+/// or nothing if file has no matches.
+pub fn edit(
+    file: &str,
+    options: &Options,
+    edit: &EditOptions,
+    searcher: &dyn Searcher,
+) -> GrepResult<Vec<Edited>> {
+    let bytes = fs::read(file).map_err(|err| ErrorType::Open {
+        path: String::from(file),
+        source: Arc::new(err),
+    })?;
+
+    let text = decoder::decode(&bytes, options.encoding);
+
+    // Invalid sequences are decoded as U+FFFD and would be lost on write
+    if decoder::encode(&text, &bytes, options.encoding).as_deref() != Some(&bytes[..]) {
+        let line = text
+            .find(char::REPLACEMENT_CHARACTER)
+            .map(|pos| text[..pos].matches('\n').count() + 1);
+        return Err(invalid_data(
+            file,
+            line,
+            "file can't be written back in its encoding",
+        ));
+    }
+
+    if options.record_separator != '\0' && text.contains('\0') {
+        return Err(invalid_data(file, None, "binary file is not edited"));
+    }
+
+    let prepared = grep::prepare(&text, options);
+    let found = grep::select(&prepared, options, searcher);
+
+    let (content, count) = replace(&text, &found, &edit.replacement, options.mode);
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+
+    let edited = Edited {
+        path: String::from(file),
+        replacements: count,
+    };
+
+    if edit.dry_run {
+        return Ok(vec![edited]);
+    }
+
+    let Some(content) = decoder::encode(&content, &bytes, options.encoding) else {
+        return Err(ErrorType::Write {
+            path: String::from(file),
+            source: Arc::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "replacement can't be written in encoding of the file",
+            )),
+        });
+    };
+
+    write_atomic(Path::new(file), &content, &edit.backup_suffix).map_err(|err| {
+        ErrorType::Write {
+            path: String::from(file),
+            source: Arc::new(err),
+        }
+    })?;
+
+    Ok(vec![edited])
+}
+
+/// Replace spans of found lines, their offsets are in the decoded text.
+/// Returns new content and number of replacements.
+fn replace(text: &str, found: &[Found], replacement: &str, mode: Mode) -> (String, usize) {
+    let mut result = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut i = 0;
+
+    for Found {
+        position, spans, ..
+    } in found
+    {
+        let spans = if matches!(mode, Mode::All) {
+            non_overlapping(spans.clone())
+        } else {
+            spans.clone()
+        };
+
+        for (l, r) in spans {
+            result.push_str(&text[i..position.offset + l]);
+            result.push_str(replacement);
+            i = position.offset + r;
+            count += 1;
+        }
+    }
+
+    result.push_str(&text[i..]);
+    (result, count)
+}
+
+/// Write into temporary file near the original one and rename it over,
+/// so readers never see a partially written file. Non empty suffix keeps a backup.
+fn write_atomic(path: &Path, content: &[u8], suffix: &str) -> io::Result<()> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let mut temp = NamedTempFile::new_in(dir.unwrap_or(Path::new(".")))?;
+
+    temp.write_all(content)?;
+    temp.as_file()
+        .set_permissions(fs::metadata(path)?.permissions())?;
+
+    if !suffix.is_empty() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        fs::copy(path, backup)?;
+    }
+
+    temp.persist(path)?;
+    Ok(())
+}
+
+fn invalid_data(file: &str, line: Option<usize>, message: &str) -> ErrorType {
+    ErrorType::Read {
+        path: String::from(file),
+        line,
+        source: Arc::new(io::Error::new(io::ErrorKind::InvalidData, message)),
+    }
+}
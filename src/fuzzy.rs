@@ -0,0 +1,227 @@
+use std::sync::Mutex;
+
+use crate::searcher::{ReverseResult, SearchResult, SearchResults, Searcher};
+
+/// Approximate search: finds parts of source within Levenshtein distance
+/// `max_errors` of the pattern (Sellers algorithm). Matches never contain
+/// record separator, so results are the same for a line and for a whole buffer.
+#[derive(Debug)]
+pub struct Fuzzy {
+    max_errors: usize,
+    separator: char,
+    scratch: Mutex<Scratch>,
+}
+
+/// Column of the distance table and start of the best match for every prefix of pattern
+#[derive(Debug, Default)]
+struct Scratch {
+    pattern: Vec<char>,
+    dist: Vec<usize>,
+    start: Vec<usize>,
+}
+
+/// Best match in the current run of ends within distance. On equal distance the longer one wins
+struct Candidate {
+    start: usize,
+    end: usize,
+    dist: usize,
+}
+
+impl Scratch {
+    fn prepare(&mut self, pattern: &str) {
+        if !self.pattern.iter().copied().eq(pattern.chars()) {
+            self.pattern.clear();
+            self.pattern.extend(pattern.chars());
+        }
+    }
+
+    /// Empty prefix of pattern matches everywhere, others are deleted from `pos`
+    fn reset(&mut self, pos: usize) {
+        self.dist.clear();
+        self.dist.extend(0..=self.pattern.len());
+        self.start.clear();
+        self.start.resize(self.pattern.len() + 1, pos);
+    }
+
+    /// Add char `c`, which ends at `next`, to the table
+    fn step(&mut self, c: char, next: usize) {
+        let (mut diag_dist, mut diag_start) = (self.dist[0], self.start[0]);
+        self.start[0] = next;
+
+        for i in 1..self.dist.len() {
+            let (up_dist, up_start) = (self.dist[i], self.start[i]);
+
+            let mut best = (
+                diag_dist + usize::from(self.pattern[i - 1] != c),
+                diag_start,
+            );
+            if self.dist[i - 1] + 1 < best.0 {
+                best = (self.dist[i - 1] + 1, self.start[i - 1]);
+            }
+            if up_dist + 1 < best.0 {
+                best = (up_dist + 1, up_start);
+            }
+
+            (self.dist[i], self.start[i]) = best;
+            (diag_dist, diag_start) = (up_dist, up_start);
+        }
+    }
+}
+
+impl Fuzzy {
+    pub fn new(max_errors: usize, separator: char) -> Self {
+        Fuzzy {
+            max_errors,
+            separator,
+            scratch: Mutex::default(),
+        }
+    }
+
+    /// Find the closest match among the first run of ends within distance, starting from `from`
+    fn next_match(&self, scratch: &mut Scratch, source: &str, from: usize) -> SearchResult {
+        let m = scratch.pattern.len();
+        scratch.reset(from);
+
+        let mut best: Option<Candidate> = None;
+
+        for (ind, c) in source[from..].char_indices() {
+            let pos = from + ind;
+
+            if c == self.separator {
+                if let Some(best) = best {
+                    return Some((best.start, best.end));
+                }
+
+                scratch.reset(pos + c.len_utf8());
+                continue;
+            }
+
+            let next = pos + c.len_utf8();
+            scratch.step(c, next);
+
+            let dist = scratch.dist[m];
+            if dist <= self.max_errors {
+                if best.as_ref().is_none_or(|best| dist <= best.dist) {
+                    best = Some(Candidate {
+                        start: scratch.start[m],
+                        end: next,
+                        dist,
+                    });
+                }
+            } else if let Some(best) = best {
+                return Some((best.start, best.end));
+            }
+        }
+
+        best.map(|best| (best.start, best.end))
+    }
+
+    /// Call `f` with every match, matches don't overlap
+    fn scan<F>(&self, pattern: &str, source: &str, mut f: F)
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let mut scratch = self.scratch.lock().unwrap_or_else(|err| err.into_inner());
+        scratch.prepare(pattern);
+
+        if scratch.pattern.is_empty() {
+            return;
+        }
+
+        let mut from = 0;
+        while from < source.len() {
+            let Some((l, r)) = self.next_match(&mut scratch, source, from) else {
+                return;
+            };
+
+            if !f(l, r) {
+                return;
+            }
+
+            from = r;
+        }
+    }
+}
+
+impl Searcher for Fuzzy {
+    fn search_left(&self, pattern: &str, source: &str) -> SearchResult {
+        let mut result = None;
+        self.scan(pattern, source, |l, r| {
+            result = Some((l, r));
+            false
+        });
+
+        result
+    }
+
+    fn search_right(&self, pattern: &str, source: &str) -> SearchResult {
+        let mut result = None;
+        self.scan(pattern, source, |l, r| {
+            result = Some((l, r));
+            true
+        });
+
+        result
+    }
+
+    fn search_all(&self, pattern: &str, source: &str) -> SearchResults {
+        let mut result = Vec::new();
+        self.scan(pattern, source, |l, r| {
+            result.push((l, r));
+            true
+        });
+
+        Some(result)
+    }
+
+    fn reverse(&self, pattern: &str, source: &str) -> ReverseResult {
+        self.search_left(pattern, source).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_match() {
+        let fuzzy = Fuzzy::new(0, '\n');
+        let result = fuzzy.search_all("aba", "abacaba");
+
+        assert_eq!(result.unwrap(), [(0, 3), (4, 7)]);
+    }
+
+    #[test]
+    fn one_typo() {
+        let fuzzy = Fuzzy::new(1, '\n');
+
+        assert_eq!(fuzzy.search_left("hello", "say helo!"), Some((4, 8)));
+        assert_eq!(fuzzy.search_left("hello", "say hallo!"), Some((4, 9)));
+        assert_eq!(fuzzy.search_left("hello", "say hellxo!"), Some((4, 10)));
+        assert_eq!(fuzzy.search_left("hello", "say hxllx!"), None);
+    }
+
+    #[test]
+    fn closest_end_is_chosen() {
+        let fuzzy = Fuzzy::new(1, '\n');
+        let result = fuzzy.search_all("abc", "xabcx abd");
+
+        assert_eq!(result.unwrap(), [(1, 4), (6, 9)]);
+    }
+
+    #[test]
+    fn no_match_across_lines() {
+        let fuzzy = Fuzzy::new(1, '\n');
+        let result = fuzzy.search_all("abc", "xab\ncx");
+
+        assert_eq!(result.unwrap(), [(1, 3)]);
+    }
+
+    #[test]
+    fn multibyte() {
+        let fuzzy = Fuzzy::new(1, '\n');
+        let result = fuzzy.search_left("привет", "всем првет");
+
+        assert_eq!(result, Some((9, 19)));
+    }
+}
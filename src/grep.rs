@@ -1,71 +1,804 @@
 use std::{
+    borrow::Cow,
+    collections::BTreeMap,
     fs::File,
-    io::{self, BufRead},
-    rc::Rc,
+    io::{self, Read},
+    iter,
+    ops::{ControlFlow, Range},
+    sync::{Arc, Mutex, mpsc},
+    thread,
 };
 
 use crate::{
-    arguments::{Algo, Args, Mode},
+    decoder, editor, fuzzy,
     grep_error::{ErrorType, GrepResult},
+    input::Input,
     kmp,
-    printer::{construct_line, construct_line_all, construct_reverse_line},
+    options::{Algo, EditOptions, Mode, Options},
+    printer::LinePosition,
     searcher::{SearchResult, Searcher},
+    walker,
 };
 
+/// Line or record selected by search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub path: String,
+    /// 1-based number of the first line
+    pub line_number: usize,
+    /// Offset of the line start in the decoded file
+    pub byte_offset: usize,
+    /// Line as it is in the file, also with `Options::ignore_case`
+    pub line: String,
+    /// Byte ranges of matches in `line`, none for lines selected by inverted match
+    pub spans: Vec<(usize, usize)>,
+}
+
+/// File changed by `edit_with_sink`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edited {
+    pub path: String,
+    pub replacements: usize,
+}
+
+/// Line or record found in prepared text, spans are relative to its start
+pub struct Found {
+    pub position: LinePosition,
+    pub len: usize,
+    pub spans: Vec<(usize, usize)>,
+}
+
 struct DataHandler<'a> {
     target: &'a str,
     line: &'a str,
-    line_pos: usize,
-    result: &'a mut Vec<String>,
-    args: &'a Args,
+    position: LinePosition,
+    result: &'a mut Vec<Found>,
+    options: &'a Options,
     searcher: &'a dyn Searcher,
 }
 
-pub fn grep(args: Args) -> GrepResult<Vec<String>> {
-    let searcher = match args.algo {
-        Algo::Kmp => kmp::KnuthMorrisPratt::default(),
-        Algo::BoyerMoore => panic!("hehehe"),
+/// Items found in a single file and its result, together with its position in found files
+type FileResult<T> = (usize, Vec<T>, GrepResult<()>);
+
+/// Passes every found item on, breaks when the rest of the file isn't needed
+type Emit<'a, T> = dyn FnMut(T) -> ControlFlow<()> + 'a;
+
+/// Bytes read from a file at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Search all files and pass every found line to `sink` and every error of a file
+/// to `report`. Lines are passed in order of files, unless `Options::as_completed` is set.
+/// With a single thread they are passed as soon as they are read, e.g. from a pipe.
+/// Result is the first error, even if something was found, except in quiet mode.
+/// Fatal errors stop the search and are only returned.
+pub fn grep_with_sink<F, E>(options: &Options, sink: F, report: E) -> GrepResult<()>
+where
+    F: FnMut(Match),
+    E: FnMut(&ErrorType),
+{
+    validate(options)?;
+
+    let threads = thread_count(options.threads);
+    let search = |file: &str, searcher: &dyn Searcher, emit: &mut Emit<Match>| {
+        sub_grep(file, options, searcher, emit)
+    };
+    run(options, threads, search, sink, report)
+}
+
+/// Replace matches in all files and pass every changed file to `sink`.
+/// Errors are handled the same way as in `grep_with_sink`, but quiet mode doesn't stop
+/// editing at the first changed file, it only passes nothing to `sink`.
+pub fn edit_with_sink<F, E>(
+    options: &Options,
+    edit: &EditOptions,
+    mut sink: F,
+    report: E,
+) -> GrepResult<()>
+where
+    F: FnMut(Edited),
+    E: FnMut(&ErrorType),
+{
+    validate(options)?;
+
+    if options.invert() {
+        return Err(ErrorType::InvalidArguments(String::from(
+            "--in-place can't be used with inverted match, it has nothing to replace",
+        )));
+    }
+
+    let select_all = Options {
+        quiet: false,
+        ..options.clone()
+    };
+    let edit_file = |file: &str, searcher: &dyn Searcher, emit: &mut Emit<Edited>| {
+        let edited = editor::edit(file, &select_all, edit, searcher)?;
+        let _ = edited.into_iter().try_for_each(emit);
+        Ok(())
     };
+    let hidden = |edited| {
+        if !options.quiet {
+            sink(edited);
+        }
+    };
+    run(&select_all, 1, edit_file, hidden, report)
+}
+
+/// Walk paths and pass items found by `per_file` in every file to `sink`.
+/// Without threads items are passed as soon as they are found.
+fn run<T, S, F, E>(
+    options: &Options,
+    threads: usize,
+    per_file: S,
+    mut sink: F,
+    mut report: E,
+) -> GrepResult<()>
+where
+    T: Send,
+    S: Fn(&str, &dyn Searcher, &mut Emit<T>) -> GrepResult<()> + Sync,
+    F: FnMut(T),
+    E: FnMut(&ErrorType),
+{
+    let mut found = false;
+    let mut error = None;
+
+    // Quiet mode only needs to know that something is found
+    let mut pass = |item: T| {
+        found = true;
+        if options.quiet {
+            return ControlFlow::Break(());
+        }
+
+        sink(item);
+        ControlFlow::Continue(())
+    };
+
+    let mut fail = |res: GrepResult<()>| {
+        match res {
+            Ok(()) => {}
+            // Invalid filters of the walk stop the search and are only returned
+            Err(err) if err.is_fatal() => {
+                error = Some(err);
+                return ControlFlow::Break(());
+            }
+            Err(err) => {
+                report(&err);
+                error.get_or_insert(err);
+            }
+        }
+
+        ControlFlow::Continue(())
+    };
+
+    let mut files = walker::walk(&options.paths, &options.walk);
+
+    // Number of walked files is unknown, explicit ones don't need more threads than files
+    let threads = if options.walk.recursive {
+        threads
+    } else {
+        threads.min(options.paths.len())
+    };
+
+    if threads <= 1 {
+        let searcher = create_searcher(options);
+        let _ = files.try_for_each(|file| {
+            let mut stop = false;
+            let res = file.and_then(|file| {
+                per_file(&file, searcher.as_ref(), &mut |item| {
+                    let flow = pass(item);
+                    stop |= flow.is_break();
+                    flow
+                })
+            });
+
+            if stop {
+                return ControlFlow::Break(());
+            }
+            fail(res)
+        });
+    } else {
+        search_parallel(files, options, threads, &per_file, |(_, items, res)| {
+            items.into_iter().try_for_each(&mut pass)?;
+            fail(res)
+        });
+    }
+
+    if let Some(err) = error.take_if(|err| err.is_fatal()) {
+        return Err(err);
+    }
+
+    if options.quiet && found {
+        return Ok(());
+    }
+
+    if let Some(err) = error {
+        return Err(err);
+    }
+
+    if !found {
+        return Err(ErrorType::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Errors of the whole search, found before any file is opened
+fn validate(options: &Options) -> GrepResult<()> {
+    if matches!(options.algo, Algo::BoyerMoore) {
+        return Err(ErrorType::InvalidArguments(String::from(
+            "boyer-moore algorithm is not implemented yet",
+        )));
+    }
+
+    if !matches!(options.algo, Algo::Fuzzy) && options.max_errors.is_some() {
+        return Err(ErrorType::InvalidArguments(String::from(
+            "--max-errors can be used only with fuzzy algorithm",
+        )));
+    }
+
+    // Empty part of the line is close enough to such pattern, so every line matches
+    let len = pattern(options).chars().count();
+    if matches!(options.algo, Algo::Fuzzy) && options.max_errors() >= len {
+        return Err(ErrorType::InvalidArguments(format!(
+            "--max-errors must be less than pattern length {len}, otherwise every line matches"
+        )));
+    }
+
+    let sep = options.record_separator;
+    if !options.multiline
+        && let Some(position) = options.pattern.chars().position(|c| c == sep)
+    {
+        return Err(ErrorType::InvalidPattern {
+            pattern: options.pattern.clone(),
+            position,
+            reason: String::from("record separator matches only with --multiline"),
+        });
+    }
+
+    Ok(())
+}
 
-    sub_grep(args, &searcher)
+/// Searchers keep scratch space between calls, so every worker creates its own
+fn create_searcher(options: &Options) -> Box<dyn Searcher> {
+    match options.algo {
+        Algo::Kmp => Box::new(kmp::KnuthMorrisPratt::default()),
+        Algo::BoyerMoore => unreachable!("rejected by validate"),
+        Algo::Fuzzy => Box::new(fuzzy::Fuzzy::new(
+            options.max_errors(),
+            options.record_separator,
+        )),
+    }
+}
+
+fn thread_count(threads: usize) -> usize {
+    match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    }
 }
 
-fn sub_grep(args: Args, searcher: &dyn Searcher) -> GrepResult<Vec<String>> {
-    match File::open(&args.file) {
-        Ok(file) => {
-            let reader = io::BufReader::new(file);
-            let data = get_update_functions(&args);
-            let target = update_string(&args.substring, &data);
+/// Search files on a pool of `threads` workers. Results are buffered until
+/// all previous files are done, so order is the same as in sequential search.
+/// Workers stop when `handle` breaks.
+fn search_parallel<T, S, F>(
+    files: walker::Files,
+    options: &Options,
+    threads: usize,
+    per_file: &S,
+    mut handle: F,
+) where
+    T: Send,
+    S: Fn(&str, &dyn Searcher, &mut Emit<T>) -> GrepResult<()> + Sync,
+    F: FnMut(FileResult<T>) -> ControlFlow<()>,
+{
+    let files = Mutex::new(files.enumerate());
+    let (sender, receiver) = mpsc::channel::<FileResult<T>>();
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let files = &files;
 
-            let mut result = Vec::new();
+            scope.spawn(move || {
+                let searcher = create_searcher(options);
 
-            for (line_pos, line) in reader.lines().map_while(Result::ok).enumerate() {
-                let line = update_string(&line, &data);
+                loop {
+                    // Workers take files from the walk one by one, it's never ahead of them
+                    let next = files.lock().unwrap_or_else(|err| err.into_inner()).next();
+                    let Some((pos, file)) = next else {
+                        break;
+                    };
 
-                let mut data_handler = DataHandler {
-                    target: &target,
-                    line: &line,
-                    line_pos,
-                    result: &mut result,
-                    args: &args,
-                    searcher,
-                };
+                    // Quiet mode needs only the first item of a file
+                    let mut items = Vec::new();
+                    let res = file.and_then(|file| {
+                        per_file(&file, searcher.as_ref(), &mut |item| {
+                            items.push(item);
+                            if options.quiet {
+                                ControlFlow::Break(())
+                            } else {
+                                ControlFlow::Continue(())
+                            }
+                        })
+                    });
+
+                    if sender.send((pos, items, res)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut next_pos = 0;
+
+        // Dropped receiver makes workers stop after current file
+        'receive: for (pos, items, res) in receiver {
+            if options.as_completed {
+                if handle((pos, items, res)).is_break() {
+                    break;
+                }
+                continue;
+            }
 
-                mode_handle(&mut data_handler);
+            pending.insert(pos, (items, res));
+            while let Some((items, res)) = pending.remove(&next_pos) {
+                if handle((next_pos, items, res)).is_break() {
+                    break 'receive;
+                }
+                next_pos += 1;
             }
+        }
+    });
+}
+
+fn sub_grep(
+    file: &str,
+    options: &Options,
+    searcher: &dyn Searcher,
+    emit: &mut Emit<Match>,
+) -> GrepResult<()> {
+    let open_error = |err| ErrorType::Open {
+        path: String::from(file),
+        source: Arc::new(err),
+    };
+    let start = LinePosition {
+        index: 0,
+        offset: 0,
+    };
+
+    let mut reader = match Input::open(file, options.mmap).map_err(open_error)? {
+        Input::Mapped(map) => {
+            let text = decoder::decode(&map, options.encoding);
+            let _ = search_part(file, &text, start, options, searcher, emit);
+            return Ok(());
+        }
+        Input::Stream(reader) => reader,
+    };
+
+    // Multiline matches and records span several lines, so they need the whole text
+    if options.multiline || options.record_mode() {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(open_error)?;
+
+        let text = decoder::decode(&bytes, options.encoding);
+        let _ = search_part(file, &text, start, options, searcher, emit);
+        return Ok(());
+    }
+
+    search_stream(file, reader, options, searcher, emit).map_err(open_error)
+}
+
+/// Search lines as soon as they are read, so output of pipes isn't delayed
+/// and quiet mode doesn't read the rest of the file
+fn search_stream(
+    file: &str,
+    mut reader: File,
+    options: &Options,
+    searcher: &dyn Searcher,
+    emit: &mut Emit<Match>,
+) -> io::Result<()> {
+    let sep = options.record_separator;
+    let mut decoder = decoder::StreamDecoder::new(options.encoding);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut text = String::new();
+    let mut position = LinePosition {
+        index: 0,
+        offset: 0,
+    };
+
+    loop {
+        let read = match reader.read(&mut chunk) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            res => res?,
+        };
+        let last = read == 0;
+        decoder.decode(&chunk[..read], last, &mut text);
+
+        // Only complete lines are searched, the rest waits for the next chunk
+        let end = if last {
+            text.len()
+        } else {
+            text.rfind(sep).map_or(0, |pos| pos + sep.len_utf8())
+        };
+
+        if end > 0 {
+            let part = &text[..end];
+            if search_part(file, part, position, options, searcher, emit).is_break() {
+                return Ok(());
+            }
+
+            position.index += part.matches(sep).count();
+            position.offset += end;
+            text.drain(..end);
+        }
+
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+/// Search decoded lines starting at `start` of the file
+fn search_part(
+    file: &str,
+    text: &str,
+    start: LinePosition,
+    options: &Options,
+    searcher: &dyn Searcher,
+    emit: &mut Emit<Match>,
+) -> ControlFlow<()> {
+    let prepared = prepare(text, options);
+
+    select(&prepared, options, searcher)
+        .into_iter()
+        .try_for_each(
+            |Found {
+                 position,
+                 len,
+                 spans,
+             }| {
+                emit(Match {
+                    path: String::from(file),
+                    line_number: start.index + position.index + 1,
+                    byte_offset: start.offset + position.offset,
+                    line: String::from(&text[position.offset..position.offset + len]),
+                    spans,
+                })
+            },
+        )
+}
+
+/// Decoded text and pattern as they are searched
+pub struct Prepared<'a> {
+    pub text: Cow<'a, str>,
+    /// Offsets of `text` in the decoded one
+    pub offsets: Offsets,
+    pub target: String,
+}
+
+/// Apply update functions to pattern and lowercase text with `Options::ignore_case`.
+/// Text is lowercased once for the whole buffer, not for every line.
+pub fn prepare<'a>(text: &'a str, options: &Options) -> Prepared<'a> {
+    let target = update_string(&pattern(options), &get_update_functions(options));
+
+    let (text, offsets) = if options.ignore_case {
+        let (folded, offsets) = fold_case(text);
+        (Cow::Owned(folded), offsets)
+    } else {
+        (Cow::Borrowed(text), Offsets::default())
+    };
+
+    Prepared {
+        text,
+        offsets,
+        target,
+    }
+}
+
+/// Lines or records selected by the mode, with offsets and spans in the decoded text
+pub fn select(prepared: &Prepared, options: &Options, searcher: &dyn Searcher) -> Vec<Found> {
+    let (text, target) = (prepared.text.as_ref(), prepared.target.as_str());
+
+    let mut result = Vec::new();
+    if options.record_mode() {
+        search_records(text, target, options, searcher, &mut result);
+    } else {
+        search_buffer(text, target, options, searcher, &mut result);
+    }
+
+    if prepared.offsets.is_empty() {
+        return result;
+    }
 
-            if result.is_empty() {
-                return Err(ErrorType::NotFound);
+    result
+        .into_iter()
+        .map(|found| {
+            let offsets = &prepared.offsets;
+            let start = found.position.offset;
+            let offset = offsets.original(start, false);
+            let spans = found
+                .spans
+                .iter()
+                .map(|&(l, r)| {
+                    let l = offsets.original(start + l, false) - offset;
+                    let r = offsets.original(start + r, true) - offset;
+                    (l, r)
+                })
+                .collect();
+
+            Found {
+                position: LinePosition {
+                    index: found.position.index,
+                    offset,
+                },
+                len: offsets.original(start + found.len, true) - offset,
+                spans,
             }
+        })
+        .collect()
+}
+
+/// In multiline mode `\n` in pattern means line break
+fn pattern(options: &Options) -> Cow<'_, str> {
+    if options.multiline {
+        Cow::Owned(options.pattern.replace("\\n", "\n"))
+    } else {
+        Cow::Borrowed(&options.pattern)
+    }
+}
+
+/// Run searcher over the whole text for candidates and derive line boundaries
+/// only around them. Only lines with candidates go to `mode_handle`.
+fn search_buffer(
+    text: &str,
+    target: &str,
+    options: &Options,
+    searcher: &dyn Searcher,
+    result: &mut Vec<Found>,
+) {
+    let sep = options.record_separator;
+
+    // Pattern with separator is rejected by `validate` without multiline mode
+    let matches = search_text(target, text, sep, searcher);
+
+    let mut matches = matches.into_iter().peekable();
+    let mut line_pos = 0;
+    let mut line_start = 0;
+
+    while let Some((left, right)) = matches.next() {
+        if options.quiet && !result.is_empty() {
+            return;
+        }
+
+        let skipped = &text[line_start..left];
+        let start = skipped
+            .rfind(sep)
+            .map_or(line_start, |pos| line_start + pos + sep.len_utf8());
+        let mut end = line_end(text, sep, left, right);
+
+        // Candidates of the same line, or of lines covered by a multiline match, form one block
+        while let Some(&(next_left, next_right)) = matches.peek()
+            && next_left <= end
+        {
+            end = end.max(line_end(text, sep, next_left, next_right));
+            matches.next();
+        }
+
+        let gap_position = LinePosition {
+            index: line_pos,
+            offset: line_start,
+        };
+        handle_gap(&text[line_start..start], gap_position, options, result);
+        line_pos += skipped.matches(sep).count();
+
+        // Same as `str::lines`, which strips `\r` only before `\n`
+        let mut line = &text[start..end];
+        if end < text.len() && sep == '\n' {
+            line = line.strip_suffix('\r').unwrap_or(line);
+        }
+
+        let mut data_handler = DataHandler {
+            target,
+            line,
+            position: LinePosition {
+                index: line_pos,
+                offset: start,
+            },
+            result,
+            options,
+            searcher,
+        };
+
+        mode_handle(&mut data_handler);
+
+        line_start = end + sep.len_utf8();
+        line_pos += line.matches(sep).count() + 1;
+    }
+
+    if line_start <= text.len() {
+        let gap_position = LinePosition {
+            index: line_pos,
+            offset: line_start,
+        };
+        handle_gap(&text[line_start..], gap_position, options, result);
+    }
+}
+
+/// Matches of `target` in the whole text. Searcher finds empty pattern only at the start
+/// of text, while it matches at the start of every line
+fn search_text(
+    target: &str,
+    text: &str,
+    sep: char,
+    searcher: &dyn Searcher,
+) -> Vec<(usize, usize)> {
+    if !target.is_empty() {
+        return searcher.search_all(target, text).unwrap_or_default();
+    }
+
+    iter::once(0)
+        .chain(text.match_indices(sep).map(|(pos, sep)| pos + sep.len()))
+        .filter(|&start| start < text.len())
+        .map(|start| (start, start))
+        .collect()
+}
+
+/// End of the record with the last char of match. Match ending with separator
+/// takes the next record too, so the separator stays inside of the block
+fn line_end(text: &str, sep: char, left: usize, right: usize) -> usize {
+    let from = if right > left && text[..right].ends_with(sep) {
+        right
+    } else {
+        text.floor_char_boundary(left.max(right.saturating_sub(1)))
+    };
+    text[from..].find(sep).map_or(text.len(), |pos| from + pos)
+}
+
+/// Like `search_buffer`, but `mode_handle` gets whole records of several lines
+fn search_records(
+    text: &str,
+    target: &str,
+    options: &Options,
+    searcher: &dyn Searcher,
+    result: &mut Vec<Found>,
+) {
+    let matches = search_text(target, text, options.record_separator, searcher);
+
+    let mut matches = matches.into_iter().peekable();
+
+    for (line_pos, range) in records(text, options, searcher) {
+        if options.quiet && !result.is_empty() {
+            return;
+        }
+
+        let mut has_candidate = false;
+        while let Some(&(left, _)) = matches.peek()
+            && left < range.end
+        {
+            has_candidate |= left >= range.start;
+            matches.next();
+        }
+
+        let position = LinePosition {
+            index: line_pos,
+            offset: range.start,
+        };
+        let record = &text[range];
+
+        if has_candidate {
+            let mut data_handler = DataHandler {
+                target,
+                line: record,
+                position,
+                result,
+                options,
+                searcher,
+            };
+
+            mode_handle(&mut data_handler);
+        } else if options.invert() {
+            result.push(Found {
+                position,
+                len: record.len(),
+                spans: Vec::new(),
+            });
+        }
+    }
+}
+
+/// Split text into records of several lines. A record starts after a blank line
+/// in paragraph mode, or at a line beginning with a match of `Options::record_start`
+/// found by the same searcher as the pattern.
+/// Returns number of the first line and bounds of every record.
+fn records(text: &str, options: &Options, searcher: &dyn Searcher) -> Vec<(usize, Range<usize>)> {
+    let sep = options.record_separator;
+    let starts = options
+        .record_start
+        .as_ref()
+        .map(|start| {
+            let start = update_string(start, &get_update_functions(options));
+            search_text(&start, text, sep, searcher)
+        })
+        .unwrap_or_default();
+
+    let mut starts = starts.into_iter().peekable();
+
+    let mut result = Vec::new();
+    let mut current = None;
+    let mut offset = 0;
+    let mut end = 0;
+
+    for (line_pos, line) in text.split_inclusive(sep).enumerate() {
+        let line_start = offset;
+        offset += line.len();
 
-            Ok(result)
+        let content = strip_separator(line, sep);
+        let blank = options.paragraph && content.trim().is_empty();
+        // Matches inside of previous lines are skipped
+        let mut new_record = false;
+        while let Some(&(left, _)) = starts.peek()
+            && left <= line_start
+        {
+            new_record |= left == line_start;
+            starts.next();
         }
-        Err(err) => Err(ErrorType::IOError(Rc::new(err))),
+
+        if (blank || new_record)
+            && let Some((pos, start)) = current.take()
+        {
+            result.push((pos, start..end));
+        }
+
+        if !blank {
+            current.get_or_insert((line_pos, line_start));
+            end = line_start + content.len();
+        }
+    }
+
+    if let Some((pos, start)) = current {
+        result.push((pos, start..end));
+    }
+
+    result
+}
+
+/// Lines between candidates have no matches, so only reverse mode prints them
+fn handle_gap(gap: &str, position: LinePosition, options: &Options, result: &mut Vec<Found>) {
+    if !options.invert() {
+        return;
+    }
+
+    let sep = options.record_separator;
+    let mut offset = position.offset;
+
+    for (ind, line) in gap.split_inclusive(sep).enumerate() {
+        let line_position = LinePosition {
+            index: position.index + ind,
+            offset,
+        };
+        offset += line.len();
+
+        result.push(Found {
+            position: line_position,
+            len: strip_separator(line, sep).len(),
+            spans: Vec::new(),
+        });
+    }
+}
+
+/// Same as `str::lines`, which strips `\r` only before `\n`
+fn strip_separator(line: &str, sep: char) -> &str {
+    match line.strip_suffix(sep) {
+        Some(content) if sep == '\n' => content.strip_suffix('\r').unwrap_or(content),
+        Some(content) => content,
+        None => line,
     }
 }
 
 fn mode_handle(data_handler: &mut DataHandler) {
-    match data_handler.args.mode {
+    match data_handler.options.mode {
+        _ if data_handler.options.invert() => handle_reverse(data_handler),
         Mode::Left => handle_left(data_handler),
         Mode::Right => handle_right(data_handler),
         Mode::Reverse => handle_reverse(data_handler),
@@ -88,20 +821,21 @@ fn handle_right(data_handler: &mut DataHandler) {
     construct_left_right(data_handler, res);
 }
 
+/// Line is selected when the mode finds nothing in it
 fn handle_reverse(data_handler: &mut DataHandler) {
-    if !data_handler
-        .searcher
-        .reverse(data_handler.target, data_handler.line)
-    {
-        return;
-    }
+    let (target, line) = (data_handler.target, data_handler.line);
+
+    let selected = match data_handler.options.mode {
+        Mode::Whole => !data_handler
+            .searcher
+            .search_left(target, line)
+            .is_some_and(|(l, r)| l == 0 && r == line.len()),
+        _ => data_handler.searcher.reverse(target, line),
+    };
 
-    let construct = construct_reverse_line(
-        String::from(data_handler.line),
-        data_handler.line_pos,
-        &data_handler.args.show_config,
-    );
-    data_handler.result.push(construct);
+    if selected {
+        push_found(data_handler, Vec::new());
+    }
 }
 
 fn handle_all(data_handler: &mut DataHandler) {
@@ -109,18 +843,8 @@ fn handle_all(data_handler: &mut DataHandler) {
         .searcher
         .search_all(data_handler.target, data_handler.line);
 
-    if let Some(vec) = res {
-        if vec.is_empty() {
-            return;
-        }
-
-        data_handler.result.push(construct_line_all(
-            data_handler.line,
-            data_handler.line_pos,
-            data_handler.target,
-            vec,
-            &data_handler.args.show_config,
-        ));
+    if let Some(vec) = res.filter(|vec| !vec.is_empty()) {
+        push_found(data_handler, vec);
     }
 }
 
@@ -129,31 +853,24 @@ fn handle_whole(data_handler: &mut DataHandler) {
         .searcher
         .search_left(data_handler.target, data_handler.line);
 
-    if let Some((l, r)) = res {
-        if l == 0 && r == data_handler.line.len() {
-            let line = construct_line(
-                l,
-                r,
-                (data_handler.line_pos, String::from(data_handler.line)),
-                &data_handler.args.show_config,
-            );
-            data_handler.result.push(line);
-        }
-    }
+    let len = data_handler.line.len();
+    construct_left_right(data_handler, res.filter(|&(l, r)| l == 0 && r == len));
 }
 
 fn construct_left_right(data_handler: &mut DataHandler, res: SearchResult) {
     if let Some(pair) = res {
-        let res = construct_line(
-            pair.0,
-            pair.1,
-            (data_handler.line_pos, String::from(data_handler.line)),
-            &data_handler.args.show_config,
-        );
-        data_handler.result.push(res);
+        push_found(data_handler, vec![pair]);
     }
 }
 
+fn push_found(data_handler: &mut DataHandler, spans: Vec<(usize, usize)>) {
+    data_handler.result.push(Found {
+        position: data_handler.position,
+        len: data_handler.line.len(),
+        spans,
+    });
+}
+
 fn update_string<F>(src: &str, funcs: &[F]) -> String
 where
     F: Fn(String) -> String,
@@ -162,12 +879,90 @@ where
     funcs.iter().fold(tmp, |acc, f| f(acc))
 }
 
-fn get_update_functions(args: &Args) -> Vec<fn(String) -> String> {
+fn get_update_functions(options: &Options) -> Vec<fn(String) -> String> {
     let mut funcs: Vec<fn(String) -> String> = Vec::new();
 
-    if args.ignore_case {
+    if options.ignore_case {
         funcs.push(|x: String| x.to_lowercase());
     }
 
     funcs
 }
+
+/// Offsets of lowercased text in the original one. Only chars whose lowercase form
+/// has another length in UTF-8 are kept, like 'İ' or Kelvin sign, others are shifted
+/// by the same amount as the last of them.
+#[derive(Debug, Default)]
+pub struct Offsets {
+    changes: Vec<Change>,
+}
+
+/// Char whose lowercase form has another length
+#[derive(Debug)]
+struct Change {
+    original: Range<usize>,
+    folded: Range<usize>,
+}
+
+impl Offsets {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Offset in the original text of `pos` in the lowercased one. Offset inside
+    /// of a changed char is moved to its start, or to its end with `end`
+    pub fn original(&self, pos: usize, end: bool) -> usize {
+        self.translate(pos, end, |change| (&change.folded, &change.original))
+    }
+
+    /// Offset in the lowercased text of `pos` in the original one
+    pub fn folded(&self, pos: usize, end: bool) -> usize {
+        self.translate(pos, end, |change| (&change.original, &change.folded))
+    }
+
+    fn translate<F>(&self, pos: usize, end: bool, sides: F) -> usize
+    where
+        F: Fn(&Change) -> (&Range<usize>, &Range<usize>),
+    {
+        let ind = self
+            .changes
+            .partition_point(|change| sides(change).0.start <= pos);
+        let Some(change) = ind.checked_sub(1).map(|ind| &self.changes[ind]) else {
+            return pos;
+        };
+
+        let (from, to) = sides(change);
+        if pos == from.start {
+            to.start
+        } else if pos < from.end {
+            if end { to.end } else { to.start }
+        } else {
+            to.end + (pos - from.end)
+        }
+    }
+}
+
+/// Lowercase text like `str::to_lowercase`, together with offsets of the original text
+pub fn fold_case(s: &str) -> (String, Offsets) {
+    let folded = s.to_lowercase();
+    let mut offsets = Offsets::default();
+    let mut pos = 0;
+
+    for (ind, c) in s.char_indices() {
+        let len = if c.is_ascii() {
+            1
+        } else {
+            c.to_lowercase().map(char::len_utf8).sum()
+        };
+
+        if len != c.len_utf8() {
+            offsets.changes.push(Change {
+                original: ind..ind + c.len_utf8(),
+                folded: pos..pos + len,
+            });
+        }
+        pos += len;
+    }
+
+    (folded, offsets)
+}
@@ -1,21 +1,114 @@
 use std::{
     error::{self},
-    rc::Rc,
+    fmt, io,
+    sync::Arc,
 };
 
+/// Errors of a search. Errors of single files don't stop search of other files
 #[derive(Debug, Clone)]
 pub enum ErrorType {
-    IOError(Rc<dyn error::Error>),
+    /// File can't be opened, or directory can't be walked. Path is empty when it's unknown
+    Open {
+        path: String,
+        source: Arc<dyn error::Error + Send + Sync>,
+    },
+    /// File can't be read as text, with 1-based line when it's known
+    Read {
+        path: String,
+        line: Option<usize>,
+        source: Arc<io::Error>,
+    },
+    /// Edited file can't be written back
+    Write {
+        path: String,
+        source: Arc<io::Error>,
+    },
+    /// Pattern can never match, position is in chars of the pattern
+    InvalidPattern {
+        pattern: String,
+        position: usize,
+        reason: String,
+    },
+    /// Options can't be used together
+    InvalidArguments(String),
     NotFound,
 }
 
 impl ErrorType {
     pub fn display(&self) {
+        eprintln!("grep-rs: {self}");
+    }
+
+    /// Error of the whole search rather than of a single file
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::InvalidPattern { .. } | Self::InvalidArguments(_)
+        )
+    }
+}
+
+impl fmt::Display for ErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::IOError(err) => println!("{err}"),
-            Self::NotFound => println!("Nothing was founded"),
+            Self::Open { path, source } if path.is_empty() => write!(f, "{source}"),
+            Self::Open { path, source } => write!(f, "{path}: {source}"),
+            Self::Read {
+                path,
+                line: Some(line),
+                source,
+            } => write!(f, "{path}:{line}: {source}"),
+            Self::Read { path, source, .. } => write!(f, "{path}: {source}"),
+            Self::Write { path, source } => write!(f, "{path}: can't write: {source}"),
+            Self::InvalidPattern {
+                pattern,
+                position,
+                reason,
+            } => write!(f, "invalid pattern '{pattern}' at {position}: {reason}"),
+            Self::InvalidArguments(message) => write!(f, "{message}"),
+            Self::NotFound => write!(f, "Nothing was founded"),
+        }
+    }
+}
+
+impl error::Error for ErrorType {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open { source, .. } => Some(source.as_ref()),
+            Self::Read { source, .. } | Self::Write { source, .. } => Some(source.as_ref()),
+            _ => None,
         }
     }
 }
 
 pub type GrepResult<T> = Result<T, ErrorType>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_shared_error<T: error::Error + Send + Sync + 'static>() {}
+
+    #[test]
+    fn messages() {
+        is_shared_error::<ErrorType>();
+
+        let read = ErrorType::Read {
+            path: String::from("a.txt"),
+            line: Some(3),
+            source: Arc::new(io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8")),
+        };
+        assert_eq!(read.to_string(), "a.txt:3: invalid UTF-8");
+        assert!(error::Error::source(&read).is_some());
+
+        let pattern = ErrorType::InvalidPattern {
+            pattern: String::from("a\nb"),
+            position: 1,
+            reason: String::from("record separator"),
+        };
+        assert_eq!(
+            pattern.to_string(),
+            "invalid pattern 'a\nb' at 1: record separator"
+        );
+    }
+}
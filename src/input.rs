@@ -0,0 +1,27 @@
+use std::{fs::File, io};
+
+use memmap2::Mmap;
+
+/// Searched file, either mapped into memory or read in chunks
+pub enum Input {
+    Mapped(Mmap),
+    Stream(File),
+}
+
+impl Input {
+    /// Map regular files when `mmap` is requested. Pipes, special files and
+    /// files that can't be mapped fall back to reading.
+    pub fn open(path: &str, mmap: bool) -> io::Result<Self> {
+        let file = File::open(path)?;
+
+        if mmap && file.metadata()?.is_file() {
+            // SAFETY: the mapping is read only. Like other grep tools we accept
+            // that a file truncated by another process during search is UB.
+            if let Ok(map) = unsafe { Mmap::map(&file) } {
+                return Ok(Self::Mapped(map));
+            }
+        }
+
+        Ok(Self::Stream(file))
+    }
+}
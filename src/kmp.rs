@@ -1,83 +1,119 @@
+use std::{ops::ControlFlow, sync::Mutex};
+
 use crate::searcher::{ReverseResult, SearchResult, SearchResults, Searcher};
 
+/// Prefix function of the pattern is kept between calls,
+/// so search over a source doesn't allocate anything
+#[derive(Debug, Default)]
+pub struct KnuthMorrisPratt {
+    scratch: Mutex<Scratch>,
+}
+
 #[derive(Debug, Default)]
-pub struct KnuthMorrisPratt {}
+struct Scratch {
+    pattern: Vec<char>,
+    prefix: Vec<usize>,
+}
+
+impl Scratch {
+    fn prepare(&mut self, pattern: &str) {
+        if self.pattern.iter().copied().eq(pattern.chars()) {
+            return;
+        }
 
-enum Side {
-    Left,
-    Right,
+        self.pattern.clear();
+        self.pattern.extend(pattern.chars());
+        prefix(&self.pattern, &mut self.prefix);
+    }
 }
 
 impl KnuthMorrisPratt {
-    fn search_core(&self, pattern: &str, source: &str, side: Side) -> SearchResult {
-        let v = pre_calc(pattern, source)?;
-
-        match side {
-            Side::Left => v[pattern.len()..]
-                .iter()
-                .enumerate()
-                .find(|(_, el)| **el == pattern.len())
-                .map(|(pos, _)| (pos - pattern.len(), pos)),
-            Side::Right => v[pattern.len()..]
-                .iter()
-                .enumerate()
-                .rev()
-                .find(|(_, el)| **el == pattern.len())
-                .map(|(pos, _)| (pos - pattern.len(), pos)),
+    /// Call `f` with byte offsets of every match, until it breaks
+    fn scan<F>(&self, pattern: &str, source: &str, mut f: F)
+    where
+        F: FnMut(usize, usize) -> ControlFlow<()>,
+    {
+        let mut scratch = self.scratch.lock().unwrap_or_else(|err| err.into_inner());
+        scratch.prepare(pattern);
+
+        let chars = &scratch.pattern;
+        let prefix = &scratch.prefix;
+
+        // Empty pattern matches at the start of source, like in grep
+        if chars.is_empty() {
+            let _ = f(0, 0);
+            return;
+        }
+
+        let mut k = 0;
+        for (ind, c) in source.char_indices() {
+            while k > 0 && chars[k] != c {
+                k = prefix[k - 1];
+            }
+
+            if chars[k] == c {
+                k += 1;
+            }
+
+            if k == chars.len() {
+                // Matched part is equal to pattern, so it has the same length in bytes
+                let end = ind + c.len_utf8();
+                if f(end - pattern.len(), end).is_break() {
+                    return;
+                }
+
+                k = prefix[k - 1];
+            }
         }
     }
 }
 
 impl Searcher for KnuthMorrisPratt {
     fn search_left(&self, pattern: &str, source: &str) -> SearchResult {
-        self.search_core(pattern, source, Side::Left)
+        let mut result = None;
+        self.scan(pattern, source, |l, r| {
+            result = Some((l, r));
+            ControlFlow::Break(())
+        });
+
+        result
     }
 
     fn search_right(&self, pattern: &str, source: &str) -> SearchResult {
-        self.search_core(pattern, source, Side::Right)
+        let mut result = None;
+        self.scan(pattern, source, |l, r| {
+            result = Some((l, r));
+            ControlFlow::Continue(())
+        });
+
+        result
     }
 
     fn search_all(&self, pattern: &str, source: &str) -> SearchResults {
-        let prefix = pre_calc(pattern, source)?;
-
-        let chars: Vec<char> = pattern.chars().collect();
         let mut result = Vec::new();
-
-        prefix[chars.len()..]
-            .iter()
-            .enumerate()
-            .filter(|(_, val)| **val == chars.len())
-            .for_each(|(ind, _)| result.push((ind - chars.len(), ind)));
+        self.scan(pattern, source, |l, r| {
+            result.push((l, r));
+            ControlFlow::Continue(())
+        });
 
         Some(result)
     }
 
     fn reverse(&self, pattern: &str, source: &str) -> ReverseResult {
-        match pre_calc(pattern, source) {
-            Some(prefix) => !prefix.contains(&pattern.len()),
-            None => false,
-        }
+        self.search_left(pattern, source).is_none()
     }
 }
 
-fn pre_calc(pattern: &str, source: &str) -> Option<Vec<usize>> {
-    let form = format!("{pattern}🤡{source}");
-    prefix(&form)
-}
-
-fn prefix(source: &str) -> Option<Vec<usize>> {
-    if source.len() <= 1 {
-        return None;
-    }
-
-    let chars = source.chars().collect::<Vec<char>>();
-    let mut result = vec![0; chars.len()];
+/// Prefix function of `chars`, written to `result` to reuse its memory
+fn prefix(chars: &[char], result: &mut Vec<usize>) {
+    result.clear();
+    result.resize(chars.len(), 0);
 
     for i in 1..chars.len() {
         let mut k = result[i - 1];
 
         while k > 0 && chars[k] != chars[i] {
-            k = result[k as usize - 1];
+            k = result[k - 1];
         }
 
         if chars[k] == chars[i] {
@@ -86,39 +122,78 @@ fn prefix(source: &str) -> Option<Vec<usize>> {
 
         result[i] = k
     }
-
-    Some(result)
 }
 
 #[cfg(test)]
 mod tests {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        cell::Cell,
+    };
+
     use super::*;
 
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts allocations of the current thread, so tests running in parallel don't interfere
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            unsafe { System.realloc(ptr, layout, new_size) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations() -> usize {
+        ALLOCATIONS.with(|count| count.get())
+    }
+
+    fn prefix_of(source: &str) -> Vec<usize> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut result = Vec::new();
+        prefix(&chars, &mut result);
+        result
+    }
+
     #[test]
     fn empty_prefix() {
-        let a = prefix("");
-        assert!(a.is_none());
+        let a = prefix_of("");
+        assert!(a.is_empty());
     }
 
     #[test]
     fn one_char_prefix() {
-        let a = prefix("a");
-        assert!(a.is_none());
+        let a = prefix_of("a");
+        assert_eq!(a, [0]);
     }
 
     #[test]
     fn simple_prefix0() {
-        let result = prefix("abbab");
+        let result = prefix_of("abbab");
 
-        assert_eq!(result.unwrap(), [0, 0, 0, 1, 2]);
+        assert_eq!(result, [0, 0, 0, 1, 2]);
     }
 
     #[test]
     fn simple_prefix1() {
-        let result = prefix("abbababb");
+        let result = prefix_of("abbababb");
 
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), [0, 0, 0, 1, 2, 1, 2, 3]);
+        assert_eq!(result, [0, 0, 0, 1, 2, 1, 2, 3]);
     }
 
     #[test]
@@ -174,4 +249,49 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap(), [(0, 3)]);
     }
+
+    #[test]
+    fn all_multibyte() {
+        let kmp = KnuthMorrisPratt::default();
+        let result = kmp.search_all("мир", "привет мир");
+
+        assert!(result.is_some());
+        assert_eq!(result.unwrap(), [(13, 19)]);
+    }
+
+    #[test]
+    fn overlapping_matches() {
+        let kmp = KnuthMorrisPratt::default();
+        let result = kmp.search_all("aba", "ababa");
+
+        assert_eq!(result.unwrap(), [(0, 3), (2, 5)]);
+    }
+
+    #[test]
+    fn empty_pattern() {
+        let kmp = KnuthMorrisPratt::default();
+
+        assert_eq!(kmp.search_left("", "aba"), Some((0, 0)));
+        assert_eq!(kmp.search_all("", "aba"), Some(vec![(0, 0)]));
+        assert!(!kmp.reverse("", "aba"));
+    }
+
+    #[test]
+    fn no_allocations_on_non_matching_lines() {
+        let kmp = KnuthMorrisPratt::default();
+        let source = "there is nothing to find in this line\n".repeat(10_000);
+
+        // First call prepares prefix function of the pattern
+        kmp.search_all("aba", &source);
+
+        let before = allocations();
+        for _ in 0..10 {
+            assert_eq!(kmp.search_all("aba", &source), Some(Vec::new()));
+            assert_eq!(kmp.search_left("aba", &source), None);
+            assert_eq!(kmp.search_right("aba", &source), None);
+            assert!(kmp.reverse("aba", &source));
+        }
+
+        assert_eq!(allocations() - before, 0);
+    }
 }
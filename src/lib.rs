@@ -0,0 +1,143 @@
+//! Substring search in files like grep, with the engine usable from other crates.
+//!
+//! Build a search with [`SearchBuilder`], or fill [`Options`] and pass every found
+//! [`Match`] to a sink with [`grep_with_sink`]. [`Printer`] shows matches as the binary
+//! prints them. Custom algorithms implement [`Searcher`].
+
+mod builder;
+mod decoder;
+mod editor;
+pub mod fuzzy;
+mod grep;
+pub mod grep_error;
+mod input;
+pub mod kmp;
+mod options;
+mod printer;
+pub mod searcher;
+pub mod style;
+mod walker;
+
+pub use builder::SearchBuilder;
+pub use fuzzy::Fuzzy;
+pub use grep::{Edited, Match, edit_with_sink, grep_with_sink};
+pub use grep_error::{ErrorType, GrepResult};
+pub use kmp::KnuthMorrisPratt;
+pub use options::{
+    Algo, ColorMode, EditOptions, Encoding, MaxColumns, Mode, Options, PrintOptions, WalkOptions,
+    WindowUnit, color_enabled,
+};
+pub use printer::Printer;
+pub use searcher::{ReverseResult, SearchResult, SearchResults, Searcher};
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    use crate::{ColorMode, Match, Mode, PrintOptions, Printer, SearchBuilder};
+
+    fn create_file(lines: Vec<&str>) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+
+        for line in lines {
+            writeln!(file, "{}", line).unwrap();
+        }
+
+        file
+    }
+
+    #[test]
+    fn search_builder() {
+        let file = create_file(vec!["Hello", "hehe", "say hello"]);
+        let path = file.path().to_str().unwrap();
+
+        let search = SearchBuilder::new("hello", [path]).ignore_case(true);
+
+        assert_eq!(
+            search.matches().unwrap(),
+            [
+                Match {
+                    path: String::from(path),
+                    line_number: 1,
+                    byte_offset: 0,
+                    line: String::from("Hello"),
+                    spans: vec![(0, 5)],
+                },
+                Match {
+                    path: String::from(path),
+                    line_number: 3,
+                    byte_offset: 11,
+                    line: String::from("say hello"),
+                    spans: vec![(4, 9)],
+                }
+            ]
+        );
+
+        let search = SearchBuilder::new("hello", [path]).mode(Mode::Reverse);
+        let lines: Vec<_> = search.matches().unwrap();
+
+        assert_eq!(lines[0].line, "Hello");
+        assert!(lines.iter().all(|found| found.spans.is_empty()));
+    }
+
+    #[test]
+    fn printer() {
+        let file = create_file(vec!["Hello", "hehe", "say hello"]);
+        let search = SearchBuilder::new("hello", [file.path().to_str().unwrap()]);
+
+        let config = PrintOptions {
+            number: true,
+            window_size: 2,
+            ..Default::default()
+        };
+        let printer = Printer::new(config, search.options());
+
+        let lines: Vec<_> = search
+            .matches()
+            .unwrap()
+            .iter()
+            .flat_map(|found| printer.lines(found))
+            .collect();
+        assert_eq!(lines, ["3:…y hello"]);
+
+        // Color mode is resolved by the printer itself, not by the binary
+        let config = PrintOptions {
+            color_mode: ColorMode::Always,
+            ..Default::default()
+        };
+        let printer = Printer::new(config, search.options());
+
+        let found = &search.matches().unwrap()[0];
+        assert_eq!(printer.lines(found), ["say \x1b[31mhello\x1b[0m"]);
+
+        // Matches keep the original line, lowercasing is only shown
+        let search = SearchBuilder::new("hello", [file.path().to_str().unwrap()]).ignore_case(true);
+        let printer = Printer::new(PrintOptions::default(), search.options());
+
+        let found = &search.matches().unwrap()[0];
+        assert_eq!(found.line, "Hello");
+        assert_eq!(printer.lines(found), ["hello"]);
+    }
+
+    #[test]
+    fn invert_match() {
+        let file = create_file(vec!["aba", "abab", "c"]);
+        let path = file.path().to_str().unwrap();
+
+        let line_numbers = |search: SearchBuilder| -> Vec<usize> {
+            let matches = search.matches().unwrap();
+            matches.iter().map(|found| found.line_number).collect()
+        };
+
+        let whole = SearchBuilder::new("aba", [path])
+            .mode(Mode::Whole)
+            .invert_match(true);
+        assert_eq!(line_numbers(whole), [2, 3]);
+
+        let inverted = SearchBuilder::new("aba", [path]).invert_match(true);
+        let reverse = SearchBuilder::new("aba", [path]).mode(Mode::Reverse);
+        assert_eq!(line_numbers(inverted), [3]);
+        assert_eq!(line_numbers(reverse), [3]);
+    }
+}
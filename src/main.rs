@@ -1,60 +1,121 @@
+mod cli;
+
 use clap::Parser;
 use std::process::ExitCode;
 
-use crate::arguments::Args;
-
-mod arguments;
-mod grep;
-mod grep_error;
-mod kmp;
-mod printer;
-mod searcher;
+use cli::Args;
+use grep_rs::{ErrorType, Mode};
 
 fn main() -> ExitCode {
     let args = Args::parse();
-    match grep::grep(args) {
-        Ok(lines) => {
-            lines.iter().for_each(|line| println!("{line}"));
-            ExitCode::SUCCESS
+
+    if matches!(args.mode, Mode::Reverse) {
+        eprintln!("grep-rs: warning: '-m reverse' is deprecated, use '-v'");
+    }
+
+    let sep = args.record_separator();
+    let report = |err: &ErrorType| {
+        if !args.no_messages {
+            err.display();
         }
+    };
+
+    // Same exit codes as GNU grep: 0 = found, 1 = not found, 2 = error
+    match cli::run(&args, |line| print!("{line}{sep}"), report) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(ErrorType::NotFound) => ExitCode::from(1),
         Err(err) => {
-            err.display();
-            ExitCode::FAILURE
+            // Errors of files are already reported
+            if err.is_fatal() {
+                err.display();
+            }
+            ExitCode::from(2)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-
-    use colored::Colorize;
     use std::{io::Write, vec};
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
-    use crate::{
-        arguments::{Algo, Args, Color, Mode, ShowConfig},
-        grep,
+    use grep_rs::{
+        Algo, ColorMode, Encoding, ErrorType, GrepResult, MaxColumns, Mode, WindowUnit,
+        color_enabled,
+        style::{Palette, Style},
     };
 
+    use crate::cli::{self, Args, ShowConfig, WalkConfig};
+
     fn create_show_config() -> ShowConfig {
         ShowConfig {
             number: false,
-            color: Color::Red,
+            column: false,
+            byte_offset: false,
+            vimgrep: false,
+            color: Some(Style::parse("red").unwrap()),
+            colors: None,
+            color_mode: ColorMode::Never,
             window_size: 10,
+            window_unit: WindowUnit::Bytes,
+            max_columns: None,
+            max_columns_preview: false,
+            replace: None,
+        }
+    }
+
+    fn create_walk_config() -> WalkConfig {
+        WalkConfig {
+            recursive: false,
+            no_ignore: false,
+            hidden: false,
+            max_depth: None,
+            follow: false,
+            one_file_system: false,
+            globs: Vec::new(),
+            types: Vec::new(),
+            type_add: Vec::new(),
         }
     }
 
     pub fn new_with_default(substring: String, file: String) -> Args {
         Args {
             substring,
-            file,
+            files: vec![file],
             mode: Mode::All,
             algo: Algo::Kmp,
+            max_errors: None,
             ignore_case: true,
+            invert_match: false,
+            multiline: false,
+            null_data: false,
+            record_separator: None,
+            paragraph: false,
+            record_start: None,
+            encoding: Encoding::Auto,
+            threads: 1,
+            as_completed: false,
+            mmap: false,
+            in_place: None,
+            dry_run: false,
+            quiet: false,
+            no_messages: false,
+            walk_config: create_walk_config(),
             show_config: create_show_config(),
         }
     }
 
+    fn grep(args: Args) -> GrepResult<Vec<String>> {
+        let mut result = Vec::new();
+        cli::run(&args, |line| result.push(line), |_| {})?;
+        Ok(result)
+    }
+
+    /// Match painted by `--color always` with the default style
+    fn red(text: &str) -> String {
+        format!("\x1b[31m{text}\x1b[0m")
+    }
+
     fn create_wanted_string(vec: Vec<String>) -> String {
         vec.iter().fold(String::new(), |acc, v| acc + v)
     }
@@ -76,23 +137,17 @@ mod tests {
 
         let mut args = new_with_default(substring, file.path().to_str().unwrap().to_string());
         args.mode = Mode::Left;
+        args.show_config.color_mode = ColorMode::Always;
 
-        match grep::grep(args) {
+        match grep(args) {
             Ok(lines) => {
                 assert_eq!(lines.len(), 2);
 
-                assert_eq!(
-                    lines[0],
-                    create_wanted_string(vec!["aba".red().to_string()])
-                );
+                assert_eq!(lines[0], create_wanted_string(vec![red("aba")]));
 
                 assert_eq!(
                     lines[1],
-                    create_wanted_string(vec![
-                        "aba".red().to_string(),
-                        "c".to_string(),
-                        "aba".to_string()
-                    ])
+                    create_wanted_string(vec![red("aba"), "c".to_string(), "aba".to_string()])
                 );
             }
             Err(_) => panic!("not expected branch"),
@@ -106,41 +161,31 @@ mod tests {
 
         let mut args = new_with_default(substring, file.path().to_str().unwrap().to_string());
         args.mode = Mode::Right;
+        args.show_config.color_mode = ColorMode::Always;
 
-        match grep::grep(args) {
+        match grep(args) {
             Ok(lines) => {
                 assert_eq!(lines.len(), 2);
 
-                assert_eq!(
-                    lines[0],
-                    create_wanted_string(vec!["aba".red().to_string()])
-                );
+                assert_eq!(lines[0], create_wanted_string(vec![red("aba")]));
 
                 assert_eq!(
                     lines[1],
-                    create_wanted_string(vec![
-                        "aba".to_string(),
-                        "c".to_string(),
-                        "aba".red().to_string()
-                    ])
+                    create_wanted_string(vec!["aba".to_string(), "c".to_string(), red("aba")])
                 );
             }
             Err(_) => panic!("not expected branch"),
         }
     }
 
-    fn test_color(
-        substring: String,
-        color_mode: Color,
-        file_data: Vec<&str>,
-        wanted: Vec<Vec<String>>,
-    ) {
+    fn test_color(substring: String, color: &str, file_data: Vec<&str>, wanted: Vec<Vec<String>>) {
         let file = create_file(file_data);
 
         let mut args = new_with_default(substring, file.path().to_str().unwrap().to_string());
-        args.show_config.color = color_mode;
+        args.show_config.color = Some(Style::parse(color).unwrap());
+        args.show_config.color_mode = ColorMode::Always;
 
-        match grep::grep(args) {
+        match grep(args) {
             Ok(lines) => {
                 assert_eq!(lines.len(), wanted.len());
 
@@ -154,16 +199,16 @@ mod tests {
 
     #[test]
     fn red_test() {
-        let first = vec!["aba".red().to_string()];
+        let first = vec!["\x1b[31maba\x1b[0m".to_string()];
         let second = vec![
-            "aba".red().to_string(),
+            "\x1b[31maba\x1b[0m".to_string(),
             "c".to_string(),
-            "aba".red().to_string(),
+            "\x1b[31maba\x1b[0m".to_string(),
         ];
 
         test_color(
             "aba".to_string(),
-            Color::Red,
+            "red",
             vec!["aba", "abacaba"],
             vec![first, second],
         );
@@ -171,15 +216,15 @@ mod tests {
 
     #[test]
     fn green_color() {
-        let first = vec!["aba".green().to_string()];
+        let first = vec!["\x1b[32maba\x1b[0m".to_string()];
         let second = vec![
-            "aba".green().to_string(),
+            "\x1b[32maba\x1b[0m".to_string(),
             "c".to_string(),
-            "aba".green().to_string(),
+            "\x1b[32maba\x1b[0m".to_string(),
         ];
         test_color(
             "aba".to_string(),
-            Color::Green,
+            "green",
             vec!["aba", "abacaba"],
             vec![first, second],
         );
@@ -187,15 +232,15 @@ mod tests {
 
     #[test]
     fn blue_color() {
-        let first = vec!["aba".blue().to_string()];
+        let first = vec!["\x1b[34maba\x1b[0m".to_string()];
         let second = vec![
-            "aba".blue().to_string(),
+            "\x1b[34maba\x1b[0m".to_string(),
             "c".to_string(),
-            "aba".blue().to_string(),
+            "\x1b[34maba\x1b[0m".to_string(),
         ];
         test_color(
             "aba".to_string(),
-            Color::Blue,
+            "blue",
             vec!["aba", "abacaba"],
             vec![first, second],
         );
@@ -208,8 +253,9 @@ mod tests {
 
         let mut args = new_with_default(substring, file.path().to_str().unwrap().to_string());
         args.show_config.number = true;
+        args.show_config.color_mode = ColorMode::Always;
 
-        match grep::grep(args) {
+        match grep(args) {
             Ok(lines) => {
                 assert_eq!(lines.len(), 2);
 
@@ -217,15 +263,18 @@ mod tests {
                 assert_eq!(
                     lines[0],
                     create_wanted_string(vec![
-                        "2:".to_string(),
-                        "hello".red().to_string(),
+                        "\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m".to_string(),
+                        red("hello"),
                         ", world".to_string(),
                     ])
                 );
 
                 assert_eq!(
                     lines[1],
-                    create_wanted_string(vec!["3:".to_string(), "hello".red().to_string(),])
+                    create_wanted_string(vec![
+                        "\x1b[32m3\x1b[0m\x1b[36m:\x1b[0m".to_string(),
+                        red("hello"),
+                    ])
                 );
             }
             Err(_) => panic!("not expected error"),
@@ -244,7 +293,7 @@ mod tests {
         let mut args = new_with_default(substring, file.path().to_str().unwrap().to_string());
         args.mode = Mode::Reverse;
 
-        match grep::grep(args) {
+        match grep(args) {
             Ok(lines) => {
                 assert_eq!(lines.len(), 1);
 
@@ -256,4 +305,1184 @@ mod tests {
             Err(_) => panic!("not expected branch"),
         }
     }
+
+    #[test]
+    fn empty_pattern() {
+        let file = create_file(vec!["aba", "", "c"]);
+        let path = file.path().to_str().unwrap();
+
+        // Like in grep, empty pattern matches at the start of every line
+        let mut args = new_with_default(String::new(), path.to_string());
+        args.show_config.number = true;
+        assert_eq!(grep(args).unwrap(), ["1:aba", "2:", "3:c"]);
+
+        let mut args = new_with_default(String::new(), path.to_string());
+        args.mode = Mode::Whole;
+        assert_eq!(grep(args).unwrap(), [""]);
+
+        let mut args = new_with_default(String::new(), path.to_string());
+        args.mode = Mode::Reverse;
+        assert!(matches!(grep(args), Err(ErrorType::NotFound)));
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(&[0xFF, 0xFE]).unwrap();
+        for unit in "no\nabacaba\n".encode_utf16() {
+            file.write_all(&unit.to_le_bytes()).unwrap();
+        }
+
+        let args = new_with_default(
+            String::from("aba"),
+            file.path().to_str().unwrap().to_string(),
+        );
+
+        match grep(args) {
+            Ok(lines) => {
+                assert_eq!(lines.len(), 1);
+
+                assert_eq!(
+                    lines[0],
+                    create_wanted_string(vec![
+                        "aba".to_string(),
+                        "c".to_string(),
+                        "aba".to_string()
+                    ])
+                );
+            }
+            Err(_) => panic!("not expected branch"),
+        }
+    }
+
+    fn multiple_files_args(threads: usize) -> (Vec<NamedTempFile>, Args) {
+        let files: Vec<NamedTempFile> = (0..8)
+            .map(|i| create_file(vec![&format!("aba{i}"), "no"]))
+            .collect();
+
+        let mut args = new_with_default(String::from("aba"), String::new());
+        args.files = files
+            .iter()
+            .map(|file| file.path().to_str().unwrap().to_string())
+            .collect();
+        args.threads = threads;
+
+        (files, args)
+    }
+
+    #[test]
+    fn parallel_keeps_order() {
+        let (files, args) = multiple_files_args(4);
+
+        match grep(args) {
+            Ok(lines) => {
+                assert_eq!(lines.len(), files.len());
+
+                for (i, file) in files.iter().enumerate() {
+                    assert_eq!(
+                        lines[i],
+                        format!("{}:aba{}", file.path().to_str().unwrap(), i)
+                    );
+                }
+            }
+            Err(_) => panic!("not expected branch"),
+        }
+    }
+
+    #[test]
+    fn parallel_as_completed() {
+        let (files, mut args) = multiple_files_args(4);
+        args.as_completed = true;
+
+        match grep(args) {
+            Ok(mut lines) => {
+                let mut wanted: Vec<String> = files
+                    .iter()
+                    .enumerate()
+                    .map(|(i, file)| format!("{}:aba{}", file.path().to_str().unwrap(), i))
+                    .collect();
+
+                lines.sort();
+                wanted.sort();
+                assert_eq!(lines, wanted);
+            }
+            Err(_) => panic!("not expected branch"),
+        }
+    }
+
+    #[test]
+    fn mmap_same_as_buffered() {
+        let file = create_file(vec![
+            "abacaba",
+            "no",
+            "ABA\r",
+            "xxabaxx aba",
+            "aba",
+            "",
+            "last aba",
+        ]);
+
+        for mode in [
+            Mode::Left,
+            Mode::Right,
+            Mode::All,
+            Mode::Reverse,
+            Mode::Whole,
+        ] {
+            for ignore_case in [false, true] {
+                let mut args =
+                    new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+                args.mode = mode;
+                args.ignore_case = ignore_case;
+                args.show_config.number = true;
+
+                let buffered = grep(args).unwrap();
+
+                let mut args =
+                    new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+                args.mode = mode;
+                args.ignore_case = ignore_case;
+                args.show_config.number = true;
+                args.mmap = true;
+
+                assert_eq!(grep(args).unwrap(), buffered);
+            }
+        }
+    }
+
+    #[test]
+    fn stream_across_chunks() {
+        // Lines and multibyte chars cross boundaries of chunks read from the file
+        let text = (0..20_000)
+            .map(|i| format!("привет {}\r\n", if i % 7 == 0 { "aba" } else { "no" }))
+            .collect::<String>();
+
+        let mut utf8 = NamedTempFile::new().unwrap();
+        utf8.write_all(text.as_bytes()).unwrap();
+        let mut utf16 = NamedTempFile::new().unwrap();
+        utf16.write_all(&[0xFF, 0xFE]).unwrap();
+        for unit in text.encode_utf16() {
+            utf16.write_all(&unit.to_le_bytes()).unwrap();
+        }
+
+        for file in [utf8, utf16] {
+            for invert_match in [false, true] {
+                let mut args =
+                    new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+                args.invert_match = invert_match;
+                args.show_config.number = true;
+                args.show_config.byte_offset = true;
+
+                let streamed = grep(args.clone()).unwrap();
+                assert_eq!(streamed.len(), if invert_match { 17_142 } else { 2_858 });
+
+                args.mmap = true;
+                assert_eq!(grep(args).unwrap(), streamed);
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_with_number() {
+        let file = create_file(vec!["first", "aba", "second", "", "abacaba", "third"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.mode = Mode::Reverse;
+        args.show_config.number = true;
+
+        match grep(args) {
+            Ok(lines) => assert_eq!(lines, ["1:first", "3:second", "4:", "6:third"]),
+            Err(_) => panic!("not expected branch"),
+        }
+    }
+
+    fn create_tree(files: Vec<(&str, &str)>) -> TempDir {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (path, data) in files {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, data).unwrap();
+        }
+
+        dir
+    }
+
+    fn found_files(args: Args) -> Vec<String> {
+        let root = args.files[0].clone();
+
+        let mut found: Vec<String> = grep(args)
+            .unwrap()
+            .iter()
+            .map(|line| line.strip_prefix(&root).unwrap())
+            .map(|path| path.split(':').next().unwrap().replace('\\', "/"))
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn recursive_respects_ignore_files() {
+        let dir = create_tree(vec![
+            (".git/HEAD", ""),
+            (".gitignore", "target/\n"),
+            (".grepignore", "*.log\n"),
+            ("a.txt", "aba"),
+            ("sub/b.txt", "aba"),
+            ("target/c.txt", "aba"),
+            ("d.log", "aba"),
+            (".hidden.txt", "aba"),
+        ]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+
+        assert_eq!(found_files(args), ["/a.txt", "/sub/b.txt"]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.no_ignore = true;
+        args.walk_config.hidden = true;
+
+        assert_eq!(
+            found_files(args),
+            [
+                "/.hidden.txt",
+                "/a.txt",
+                "/d.log",
+                "/sub/b.txt",
+                "/target/c.txt"
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_and_type_filters() {
+        let dir = create_tree(vec![
+            ("main.rs", "aba"),
+            ("main_test.rs", "aba"),
+            ("script.py", "aba"),
+            ("page.html", "aba"),
+            ("notes.txt", "aba"),
+        ]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.globs = vec![String::from("!*_test.rs")];
+        args.walk_config.types = vec![String::from("rust"), String::from("web")];
+        args.walk_config.type_add = vec![String::from("web:*.html")];
+
+        assert_eq!(found_files(args), ["/main.rs", "/page.html"]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.globs = vec![String::from("*.py"), String::from("*.txt")];
+
+        assert_eq!(found_files(args), ["/notes.txt", "/script.py"]);
+    }
+
+    #[test]
+    fn globs_relative_to_root() {
+        let dir = create_tree(vec![
+            ("src/a.rs", "aba"),
+            ("src/b/b.rs", "aba"),
+            ("c.py", "aba"),
+        ]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.globs = vec![String::from("src/*.rs")];
+
+        assert_eq!(found_files(args), ["/src/a.rs"]);
+
+        // Explicitly given files are searched whatever the filters are
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().join("c.py").to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.types = vec![String::from("rust")];
+
+        assert_eq!(grep(args).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn max_depth() {
+        let dir = create_tree(vec![
+            ("a.txt", "aba"),
+            ("b/b.txt", "aba"),
+            ("b/c/c.txt", "aba"),
+        ]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.max_depth = Some(2);
+
+        assert_eq!(found_files(args), ["/a.txt", "/b/b.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_special_files() {
+        let dir = create_tree(vec![("a.txt", "aba")]);
+        let _socket = std::os::unix::net::UnixListener::bind(dir.path().join("socket")).unwrap();
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+
+        assert_eq!(found_files(args), ["/a.txt"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_symlink_loop() {
+        let dir = create_tree(vec![("a/a.txt", "aba")]);
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a/loop")).unwrap();
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.follow = true;
+
+        let mut lines = Vec::new();
+        let mut reported = 0;
+        let res = cli::run(&args, |line| lines.push(line), |_| reported += 1);
+
+        // Loop is reported with its path, but doesn't stop the search
+        assert!(matches!(
+            res,
+            Err(ErrorType::Open { path, .. }) if path.ends_with("loop")
+        ));
+        assert_eq!(reported, 1);
+        assert_eq!(lines.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quiet_stops_walk() {
+        let dir = create_tree(vec![("a/a.txt", "aba")]);
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a/loop")).unwrap();
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.walk_config.follow = true;
+        args.quiet = true;
+
+        // Loop is walked after the match, so it's never reached
+        for threads in [1, 2] {
+            args.threads = threads;
+
+            let mut reported = 0;
+            let res = cli::run(&args, |_| {}, |_| reported += 1);
+
+            assert!(res.is_ok());
+            assert_eq!(reported, 0);
+        }
+    }
+
+    #[test]
+    fn replace_preview() {
+        let file = create_file(vec!["abacaba", "no"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.show_config.replace = Some(String::from("X"));
+        args.show_config.color_mode = ColorMode::Always;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                red("X"),
+                "c".to_string(),
+                red("X")
+            ])]
+        );
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.mode = Mode::Right;
+        args.show_config.replace = Some(String::from("X"));
+        args.show_config.color_mode = ColorMode::Always;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec!["abac".to_string(), red("X")])]
+        );
+    }
+
+    #[test]
+    fn replace_overlapping_matches() {
+        let dir = create_tree(vec![("a.txt", "ababa\n")]);
+        let path = dir.path().join("a.txt");
+
+        let mut args = new_with_default(String::from("aba"), path.to_str().unwrap().to_string());
+        args.mode = Mode::All;
+        args.show_config.replace = Some(String::from("X"));
+
+        assert_eq!(
+            grep(args.clone()).unwrap(),
+            [create_wanted_string(vec![
+                "X".to_string(),
+                "ba".to_string()
+            ])]
+        );
+
+        args.in_place = Some(String::new());
+        grep(args).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "Xba\n");
+    }
+
+    #[test]
+    fn in_place_with_backup() {
+        let dir = create_tree(vec![("a.txt", "Aba abacaba\r\nno\nabab\n")]);
+        let path = dir.path().join("a.txt");
+
+        let mut args = new_with_default(String::from("aba"), path.to_str().unwrap().to_string());
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::from(".bak"));
+
+        let lines = grep(args).unwrap();
+
+        assert_eq!(
+            lines,
+            [format!("{}: 4 replacements", path.to_str().unwrap())]
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "X XcX\r\nno\nXb\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt.bak")).unwrap(),
+            "Aba abacaba\r\nno\nabab\n"
+        );
+    }
+
+    #[test]
+    fn in_place_dry_run_and_binary() {
+        let dir = create_tree(vec![("a.txt", "aba\n"), ("b.bin", "aba\0")]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().join("a.txt").to_str().unwrap().to_string(),
+        );
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+        args.dry_run = true;
+
+        assert_eq!(grep(args).unwrap().len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "aba\n"
+        );
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().join("b.bin").to_str().unwrap().to_string(),
+        );
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+
+        assert!(grep(args).is_err());
+        assert_eq!(std::fs::read(dir.path().join("b.bin")).unwrap(), b"aba\0");
+    }
+
+    #[test]
+    fn in_place_multiline() {
+        let dir = create_tree(vec![("a.conf", "key =\n  value\nother =\n  value\n")]);
+        let path = dir.path().join("a.conf");
+
+        let mut args = new_with_default(
+            String::from("key =\\n  value"),
+            path.to_str().unwrap().to_string(),
+        );
+        args.multiline = true;
+        args.show_config.replace = Some(String::from("key = value"));
+        args.in_place = Some(String::new());
+
+        assert_eq!(grep(args).unwrap().len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "key = value\nother =\n  value\n"
+        );
+    }
+
+    #[test]
+    fn in_place_quiet() {
+        let dir = create_tree(vec![("a.txt", "aba\n"), ("b.txt", "aba\n")]);
+
+        let mut args = new_with_default(
+            String::from("aba"),
+            dir.path().to_str().unwrap().to_string(),
+        );
+        args.walk_config.recursive = true;
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+        args.quiet = true;
+
+        // Quiet mode hides the summary, but every file is edited
+        assert!(grep(args).unwrap().is_empty());
+        for name in ["a.txt", "b.txt"] {
+            assert_eq!(
+                std::fs::read_to_string(dir.path().join(name)).unwrap(),
+                "X\n"
+            );
+        }
+    }
+
+    #[test]
+    fn in_place_keeps_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let utf16 = dir.path().join("utf16.txt");
+        let invalid = dir.path().join("invalid.txt");
+        std::fs::write(&utf16, b"\xFF\xFEa\0b\0a\0\n\0").unwrap();
+        std::fs::write(&invalid, b"aba\n\xFF aba\n").unwrap();
+
+        let mut args = new_with_default(String::from("aba"), utf16.to_str().unwrap().to_string());
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+
+        assert_eq!(grep(args).unwrap().len(), 1);
+        assert_eq!(std::fs::read(&utf16).unwrap(), b"\xFF\xFEX\0\n\0");
+
+        // Invalid UTF-8 can't be written back as it was
+        let mut args = new_with_default(String::from("aba"), invalid.to_str().unwrap().to_string());
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+
+        assert!(matches!(
+            grep(args),
+            Err(ErrorType::Read { line: Some(2), .. })
+        ));
+        assert_eq!(std::fs::read(&invalid).unwrap(), b"aba\n\xFF aba\n");
+    }
+
+    #[test]
+    fn max_errors_validation() {
+        let file = create_file(vec!["aba"]);
+        let path = file.path().to_str().unwrap().to_string();
+
+        let mut args = new_with_default(String::from("aba"), path.clone());
+        args.algo = Algo::Fuzzy;
+        args.max_errors = Some(3);
+        assert!(matches!(grep(args), Err(ErrorType::InvalidArguments(_))));
+
+        let mut args = new_with_default(String::from("aba"), path);
+        args.max_errors = Some(1);
+        assert!(matches!(grep(args), Err(ErrorType::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn fuzzy_with_modes() {
+        let file = create_file(vec!["helo world", "nothing", "hallo and hello"]);
+
+        let mut args = new_with_default(
+            "hello".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.algo = Algo::Fuzzy;
+        args.show_config.color_mode = ColorMode::Always;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [
+                create_wanted_string(vec![red("helo"), " world".to_string()]),
+                create_wanted_string(vec![red("hallo"), " and ".to_string(), red("hello")])
+            ]
+        );
+
+        let mut args = new_with_default(
+            "hello".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.algo = Algo::Fuzzy;
+        args.mode = Mode::Reverse;
+
+        assert_eq!(grep(args).unwrap(), ["nothing"]);
+    }
+
+    #[test]
+    fn multiline() {
+        let file = create_file(vec!["key =", "  value", "other", "key =", "  x"]);
+
+        let mut args = new_with_default(
+            "key =\\n  value".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.multiline = true;
+        args.show_config.number = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "1:".to_string(),
+                "key =\n  value".to_string()
+            ])]
+        );
+
+        let mut args = new_with_default(
+            "=\\n  ".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.multiline = true;
+        args.mode = Mode::Reverse;
+        args.show_config.number = true;
+
+        assert_eq!(grep(args).unwrap(), ["3:other"]);
+    }
+
+    #[test]
+    fn multiline_pattern_ends_with_separator() {
+        let file = create_file(vec!["key =", "  value", "foo"]);
+        let path = file.path().to_str().unwrap();
+
+        // Separator at the end of match takes the next line into the block
+        let mut args = new_with_default("value\\n".to_string(), path.to_string());
+        args.multiline = true;
+        args.show_config.number = true;
+        assert_eq!(grep(args).unwrap(), ["2:  value\nfoo"]);
+
+        let mut args = new_with_default("value\\n".to_string(), path.to_string());
+        args.multiline = true;
+        args.invert_match = true;
+        assert_eq!(grep(args).unwrap(), ["key ="]);
+    }
+
+    #[test]
+    fn record_separators() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"first\0second aba\nline\0aba").unwrap();
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.null_data = true;
+        args.show_config.number = true;
+        args.mode = Mode::Reverse;
+
+        assert_eq!(grep(args).unwrap(), ["1:first"]);
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("one\x1eaba\ntwo\x1ethree\x1e".as_bytes())
+            .unwrap();
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.record_separator = Some('\x1e');
+        args.show_config.number = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "2:".to_string(),
+                "aba".to_string(),
+                "\ntwo".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn records_by_start() {
+        let file = create_file(vec![
+            "2024-01-01 INFO start",
+            "2024-01-01 ERROR boom",
+            "  at Foo.bar",
+            "  at NullPointer",
+            "2024-01-02 INFO ok",
+        ]);
+
+        let mut args = new_with_default(
+            "NullPointer".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.ignore_case = false;
+        args.record_start = Some(String::from("2024-"));
+        args.show_config.number = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "2:2024-01-01 ERROR boom\n  at Foo.bar\n  at ".to_string(),
+                "NullPointer".to_string()
+            ])]
+        );
+
+        // Window doesn't trim records in other modes either
+        let mut args =
+            new_with_default("at".to_string(), file.path().to_str().unwrap().to_string());
+        args.ignore_case = false;
+        args.mode = Mode::Left;
+        args.record_start = Some(String::from("2024-"));
+        args.show_config.number = true;
+        args.show_config.window_size = 3;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "2:2024-01-01 ERROR boom\n  ".to_string(),
+                "at".to_string(),
+                " Foo.bar\n  at NullPointer".to_string()
+            ])]
+        );
+
+        // Fuzzy start matches other dates too, but only at the beginning of a line
+        let file = create_file(vec![
+            "start",
+            "2023-12-31 INFO 2024-01-01",
+            "  at NullPointer",
+            "2024-01-02 INFO ok",
+        ]);
+
+        let mut args = new_with_default(
+            "NullPointer".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        );
+        args.ignore_case = false;
+        args.algo = Algo::Fuzzy;
+        args.invert_match = true;
+        args.record_start = Some(String::from("2024-"));
+        args.show_config.number = true;
+
+        assert_eq!(grep(args).unwrap(), ["1:start", "4:2024-01-02 INFO ok"]);
+    }
+
+    #[test]
+    fn paragraphs() {
+        let file = create_file(vec!["first", "aba", "", "", "second", "third", ""]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.paragraph = true;
+        args.mode = Mode::Reverse;
+        args.show_config.number = true;
+
+        assert_eq!(grep(args).unwrap(), ["5:second\nthird"]);
+    }
+
+    #[test]
+    fn column_and_byte_offset() {
+        let file = create_file(vec!["no", "xxaba"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.show_config.number = true;
+        args.show_config.column = true;
+        args.show_config.byte_offset = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "2:3:5:xx".to_string(),
+                "aba".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn byte_offset_ignore_case() {
+        // Lowercase 'İ' is longer in UTF-8, offsets still point into the original line
+        let file = create_file(vec!["İİ ABA"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.ignore_case = true;
+        args.show_config.column = true;
+        args.show_config.byte_offset = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "6:5:i\u{307}i\u{307} ".to_string(),
+                "aba".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn ignore_case_changes_length() {
+        // Lowercase of Kelvin sign and capital sharp s is shorter in UTF-8
+        let file = create_file(vec!["\u{212A}elvin", "STRASSE \u{1E9E}"]);
+        let path = file.path().to_str().unwrap();
+
+        let mut args = new_with_default("kelvin".to_string(), path.to_string());
+        args.ignore_case = true;
+        args.show_config.byte_offset = true;
+        assert_eq!(grep(args).unwrap(), ["0:kelvin"]);
+
+        let mut args = new_with_default("ß".to_string(), path.to_string());
+        args.ignore_case = true;
+        args.show_config.column = true;
+        args.show_config.byte_offset = true;
+        assert_eq!(grep(args).unwrap(), ["9:17:strasse ß"]);
+
+        let dir = create_tree(vec![("a.txt", "\u{212A}elvin STRASSE \u{1E9E}\n")]);
+        let path = dir.path().join("a.txt");
+
+        let mut args = new_with_default("kelvin".to_string(), path.to_str().unwrap().to_string());
+        args.ignore_case = true;
+        args.show_config.replace = Some(String::from("K"));
+        args.in_place = Some(String::new());
+        grep(args).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "K STRASSE \u{1E9E}\n"
+        );
+    }
+
+    #[test]
+    fn vimgrep() {
+        let file = create_file(vec!["a long line with aba and aba"]);
+        let path = file.path().to_str().unwrap().to_string();
+
+        let mut args = new_with_default("aba".to_string(), path.clone());
+        args.show_config.vimgrep = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [
+                create_wanted_string(vec![
+                    format!("{path}:1:18:a long line with "),
+                    "aba".to_string(),
+                    " and aba".to_string()
+                ]),
+                create_wanted_string(vec![
+                    format!("{path}:1:26:a long line with aba and "),
+                    "aba".to_string()
+                ])
+            ]
+        );
+    }
+
+    #[test]
+    fn never_color() {
+        let file = create_file(vec!["abacaba"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.show_config.color_mode = ColorMode::Never;
+
+        assert_eq!(grep(args).unwrap(), ["abacaba"]);
+    }
+
+    #[test]
+    fn color_mode_resolution() {
+        let set = || Some(std::ffi::OsString::from("1"));
+        let zero = || Some(std::ffi::OsString::from("0"));
+
+        assert!(color_enabled(ColorMode::Always, set(), None, false));
+        assert!(!color_enabled(ColorMode::Never, None, set(), true));
+        assert!(color_enabled(ColorMode::Auto, None, None, true));
+        assert!(!color_enabled(ColorMode::Auto, None, None, false));
+        assert!(!color_enabled(ColorMode::Auto, set(), None, true));
+        assert!(color_enabled(ColorMode::Auto, set(), set(), false));
+        assert!(!color_enabled(ColorMode::Auto, None, zero(), false));
+    }
+
+    #[test]
+    fn styled_match() {
+        let file = create_file(vec!["abacaba"]);
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.show_config.color = Some(Style::parse("bold,underline,bg=blue").unwrap());
+        args.show_config.color_mode = ColorMode::Always;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "\x1b[1;4;44maba\x1b[0m".to_string(),
+                "c".to_string(),
+                "\x1b[1;4;44maba\x1b[0m".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn styled_prefix() {
+        let file = create_file(vec!["xabx"]);
+
+        let mut args =
+            new_with_default("ab".to_string(), file.path().to_str().unwrap().to_string());
+        args.show_config.number = true;
+        args.show_config.color_mode = ColorMode::Always;
+        args.show_config.colors = Some(Palette::parse("ln=blue:se=bold:sl=underline").unwrap());
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "\x1b[34m1\x1b[0m".to_string(),
+                "\x1b[1m:\x1b[0m".to_string(),
+                "\x1b[4mx\x1b[0m".to_string(),
+                "\x1b[31mab\x1b[0m".to_string(),
+                "\x1b[4mx\x1b[0m".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn windows_in_all_mode() {
+        let file = create_file(vec!["0123456789ab0123456789ab", "xxxxxabyabxxxxx"]);
+
+        let args_with_mode = |mode| {
+            let mut args =
+                new_with_default("ab".to_string(), file.path().to_str().unwrap().to_string());
+            args.mode = mode;
+            args.show_config.window_size = 2;
+            args
+        };
+
+        assert_eq!(
+            grep(args_with_mode(Mode::All)).unwrap(),
+            [
+                create_wanted_string(vec![
+                    "…89".to_string(),
+                    "ab".to_string(),
+                    "01…89".to_string(),
+                    "ab".to_string()
+                ]),
+                create_wanted_string(vec![
+                    "…xx".to_string(),
+                    "ab".to_string(),
+                    "y".to_string(),
+                    "ab".to_string(),
+                    "xx…".to_string()
+                ])
+            ]
+        );
+
+        assert_eq!(
+            grep(args_with_mode(Mode::Left)).unwrap(),
+            [
+                create_wanted_string(vec!["…89".to_string(), "ab".to_string(), "01…".to_string()]),
+                create_wanted_string(vec!["…xx".to_string(), "ab".to_string(), "ya…".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_by_unit() {
+        let file = create_file(vec![
+            "e\u{301}e\u{301}e\u{301}abe\u{301}e\u{301}",
+            "日本語xbc日本語",
+        ]);
+
+        let grep_with_unit = |substring: &str, size, unit| {
+            let mut args = new_with_default(
+                substring.to_string(),
+                file.path().to_str().unwrap().to_string(),
+            );
+            args.show_config.window_size = size;
+            args.show_config.window_unit = unit;
+            grep(args).unwrap()
+        };
+
+        // Graphemes are not split even when window is in bytes
+        assert_eq!(
+            grep_with_unit("ab", 2, WindowUnit::Bytes),
+            [create_wanted_string(vec![
+                "…e\u{301}".to_string(),
+                "ab".to_string(),
+                "e\u{301}…".to_string()
+            ])]
+        );
+
+        assert_eq!(
+            grep_with_unit("ab", 2, WindowUnit::Graphemes),
+            [create_wanted_string(vec![
+                "…e\u{301}e\u{301}".to_string(),
+                "ab".to_string(),
+                "e\u{301}e\u{301}".to_string()
+            ])]
+        );
+
+        // Wide character takes two columns
+        assert_eq!(
+            grep_with_unit("xb", 3, WindowUnit::Columns),
+            [create_wanted_string(vec![
+                "…語".to_string(),
+                "xb".to_string(),
+                "c日…".to_string()
+            ])]
+        );
+    }
+
+    #[test]
+    fn max_columns() {
+        let long = format!("{0}ab{0}ab{0}", "x".repeat(10));
+        let file = create_file(vec![&long, &"y".repeat(25), "ab"]);
+
+        let grep_with_mode = |mode, preview| {
+            let mut args =
+                new_with_default("ab".to_string(), file.path().to_str().unwrap().to_string());
+            args.mode = mode;
+            args.show_config.max_columns = Some(MaxColumns::Fixed(20));
+            args.show_config.max_columns_preview = preview;
+            grep(args).unwrap()
+        };
+
+        assert_eq!(
+            grep_with_mode(Mode::All, false),
+            ["[Omitted long line]".to_string(), "ab".to_string()]
+        );
+
+        assert_eq!(
+            grep_with_mode(Mode::All, true),
+            [
+                create_wanted_string(vec![
+                    format!("…{}", "x".repeat(9)),
+                    "ab".to_string(),
+                    format!("{}…", "x".repeat(9))
+                ]),
+                "ab".to_string()
+            ]
+        );
+
+        assert_eq!(
+            grep_with_mode(Mode::Reverse, false),
+            ["[Omitted long line]"]
+        );
+        assert_eq!(
+            grep_with_mode(Mode::Reverse, true),
+            [format!("{}…", "y".repeat(20))]
+        );
+    }
+
+    #[test]
+    fn quiet_and_errors() {
+        let file = create_file(vec!["aba", "aba"]);
+        let path = file.path().to_str().unwrap().to_string();
+
+        let mut args = new_with_default("aba".to_string(), path.clone());
+        args.files.insert(0, String::from("/nonexistent/file"));
+
+        let mut reported = 0;
+        let res = cli::run(&args, |_| {}, |_| reported += 1);
+
+        // Error is returned even when something was found
+        assert!(matches!(
+            res,
+            Err(ErrorType::Open { path, .. }) if path == "/nonexistent/file"
+        ));
+        assert_eq!(reported, 1);
+
+        args.quiet = true;
+        args.threads = 2;
+
+        let mut lines = Vec::new();
+        let res = cli::run(&args, |line| lines.push(line), |_| {});
+
+        assert!(res.is_ok());
+        assert!(lines.is_empty());
+
+        let mut args = new_with_default("xyz".to_string(), path);
+        args.quiet = true;
+
+        assert!(matches!(grep(args), Err(ErrorType::NotFound)));
+    }
+
+    #[test]
+    fn structured_errors() {
+        let dir = create_tree(vec![("a.txt", "aba\n")]);
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+        std::fs::write(path("b.txt"), b"ok\naba\xff\n").unwrap();
+
+        let args = new_with_default(String::from("a\nb"), path("a.txt"));
+        assert!(matches!(
+            grep(args),
+            Err(ErrorType::InvalidPattern { position: 1, .. })
+        ));
+
+        let mut args = new_with_default(String::from("aba"), path("a.txt"));
+        args.mode = Mode::Reverse;
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+        assert!(matches!(grep(args), Err(ErrorType::InvalidArguments(_))));
+
+        // Other files are edited despite the failed one
+        let mut args = new_with_default(String::from("aba"), path("b.txt"));
+        args.files.push(path("a.txt"));
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+
+        let mut errors = Vec::new();
+        let res = cli::run(&args, |_| {}, |err| errors.push(err.to_string()));
+
+        assert!(matches!(res, Err(ErrorType::Read { line: Some(2), .. })));
+        assert_eq!(
+            errors,
+            [format!(
+                "{}:2: file can't be written back in its encoding",
+                path("b.txt")
+            )]
+        );
+        assert_eq!(std::fs::read_to_string(path("a.txt")).unwrap(), "X\n");
+    }
+
+    #[test]
+    fn invert_match() {
+        let file = create_file(vec!["aba", "abab", "c"]);
+        let path = file.path().to_str().unwrap();
+
+        let mut args = new_with_default("aba".to_string(), path.to_string());
+        args.mode = Mode::Whole;
+        args.invert_match = true;
+        args.show_config.number = true;
+        assert_eq!(grep(args).unwrap(), ["2:abab", "3:c"]);
+
+        let mut inverted = new_with_default("aba".to_string(), path.to_string());
+        inverted.invert_match = true;
+        let mut reverse = new_with_default("aba".to_string(), path.to_string());
+        reverse.mode = Mode::Reverse;
+        assert_eq!(grep(inverted).unwrap(), ["c"]);
+        assert_eq!(grep(reverse).unwrap(), ["c"]);
+
+        let mut args = new_with_default("aba".to_string(), path.to_string());
+        args.invert_match = true;
+        args.show_config.replace = Some(String::from("X"));
+        args.in_place = Some(String::new());
+        assert!(matches!(grep(args), Err(ErrorType::InvalidArguments(_))));
+    }
+
+    #[test]
+    fn multibyte_record_separator() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all("a§aba§c".as_bytes()).unwrap();
+
+        let mut args =
+            new_with_default("aba".to_string(), file.path().to_str().unwrap().to_string());
+        args.record_separator = Some('§');
+        args.show_config.number = true;
+
+        assert_eq!(
+            grep(args).unwrap(),
+            [create_wanted_string(vec![
+                "2:".to_string(),
+                "aba".to_string()
+            ])]
+        );
+    }
 }
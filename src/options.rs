@@ -0,0 +1,279 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, IsTerminal},
+    sync::OnceLock,
+};
+
+use clap::ValueEnum;
+use terminal_size::{Width, terminal_size};
+
+use crate::style::{Palette, Style};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Mode {
+    Left,
+    Right,
+    All,
+    /// Deprecated, same as --invert-match
+    Reverse,
+    Whole,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Algo {
+    Kmp,
+    BoyerMoore,
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Limit of shown line width in terminal columns
+#[derive(Debug, Clone, Copy)]
+pub enum MaxColumns {
+    /// Width of terminal, no limit when output is not a terminal
+    Auto,
+    Fixed(usize),
+}
+
+/// What `window_size` counts
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum WindowUnit {
+    Bytes,
+    /// User-perceived characters
+    Graphemes,
+    /// Terminal columns, wide characters take two
+    Columns,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Encoding {
+    Auto,
+    Utf8,
+    Utf16le,
+    Utf16be,
+    Latin1,
+    Windows1251,
+    Koi8r,
+}
+
+/// What and where to search. Fields are added over time, so it's created by `Options::new`
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Options {
+    pub pattern: String,
+    pub paths: Vec<String>,
+    pub mode: Mode,
+    pub algo: Algo,
+    /// Maximum edit distance of found part for fuzzy algorithm, 1 by default
+    pub max_errors: Option<usize>,
+    pub ignore_case: bool,
+    /// Select lines without match of the mode
+    pub invert_match: bool,
+    /// Allow matches across lines, '\n' in pattern means line break
+    pub multiline: bool,
+    pub record_separator: char,
+    /// Search records of lines separated by blank lines
+    pub paragraph: bool,
+    /// Start a new record at every line beginning with a match of this pattern
+    pub record_start: Option<String>,
+    pub encoding: Encoding,
+    /// Number of threads for searching files. 0 = number of available cores
+    pub threads: usize,
+    /// Pass results of files as soon as they are completed, instead of in the given order
+    pub as_completed: bool,
+    pub mmap: bool,
+    /// Stop at the first match, nothing is passed to sink
+    pub quiet: bool,
+    pub walk: WalkOptions,
+}
+
+impl Options {
+    pub fn new<I, S>(pattern: &str, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Options {
+            pattern: String::from(pattern),
+            paths: paths.into_iter().map(Into::into).collect(),
+            mode: Mode::All,
+            algo: Algo::Kmp,
+            max_errors: None,
+            ignore_case: false,
+            invert_match: false,
+            multiline: false,
+            record_separator: '\n',
+            paragraph: false,
+            record_start: None,
+            encoding: Encoding::Auto,
+            threads: 1,
+            as_completed: false,
+            mmap: false,
+            quiet: false,
+            walk: WalkOptions::default(),
+        }
+    }
+
+    /// Deprecated `Mode::Reverse` is the same as inverted `Mode::All`
+    pub fn invert(&self) -> bool {
+        self.invert_match || matches!(self.mode, Mode::Reverse)
+    }
+
+    /// Lines are grouped into paragraphs or records with start pattern
+    pub fn record_mode(&self) -> bool {
+        self.paragraph || self.record_start.is_some()
+    }
+
+    /// Edit distance of fuzzy algorithm
+    pub fn max_errors(&self) -> usize {
+        self.max_errors.unwrap_or(1)
+    }
+}
+
+/// Which files are found in directories
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct WalkOptions {
+    /// Search files in directories recursively
+    pub recursive: bool,
+    /// Don't respect .gitignore, .git/info/exclude, global git excludes and .grepignore
+    pub no_ignore: bool,
+    pub hidden: bool,
+    /// Descend at most this number of directories below given paths
+    pub max_depth: Option<usize>,
+    pub follow: bool,
+    pub one_file_system: bool,
+    /// Globs of walked files, glob starting with '!' excludes files
+    pub globs: Vec<String>,
+    /// Names of file types, e.g. rust, py, js
+    pub types: Vec<String>,
+    /// File types in format 'name:glob'
+    pub type_add: Vec<String>,
+}
+
+/// Replacement of matches written into files
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct EditOptions {
+    pub replacement: String,
+    /// Non empty suffix keeps backup of original file
+    pub backup_suffix: String,
+    /// Count replacements without writing files
+    pub dry_run: bool,
+}
+
+impl EditOptions {
+    pub fn new(replacement: &str) -> Self {
+        EditOptions {
+            replacement: String::from(replacement),
+            backup_suffix: String::new(),
+            dry_run: false,
+        }
+    }
+}
+
+/// How `Printer` shows found lines. Not colored by default
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PrintOptions {
+    pub number: bool,
+    /// 1-based column of the first shown match in line
+    pub column: bool,
+    /// Byte offset of the first shown match in the decoded file
+    pub byte_offset: bool,
+    /// Every match as 'path:line:column:text'
+    pub vimgrep: bool,
+    /// Style of matches, before `colors`
+    pub color: Option<Style>,
+    pub colors: Option<Palette>,
+    pub color_mode: ColorMode,
+    /// Size of window around each match, trimmed parts are marked with '…'
+    pub window_size: usize,
+    pub window_unit: WindowUnit,
+    /// Omit lines wider than this
+    pub max_columns: Option<MaxColumns>,
+    /// Show long lines trimmed around the first match instead of omitting them
+    pub max_columns_preview: bool,
+    /// Show matched part replaced with this string
+    pub replace: Option<String>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            number: false,
+            column: false,
+            byte_offset: false,
+            vimgrep: false,
+            color: None,
+            colors: None,
+            color_mode: ColorMode::Never,
+            window_size: 10,
+            window_unit: WindowUnit::Bytes,
+            max_columns: None,
+            max_columns_preview: false,
+            replace: None,
+        }
+    }
+}
+
+impl PrintOptions {
+    /// Environment and terminal don't change during search, so auto mode is resolved once
+    pub fn use_color(&self) -> bool {
+        static AUTO: OnceLock<bool> = OnceLock::new();
+
+        match self.color_mode {
+            ColorMode::Auto => *AUTO.get_or_init(|| {
+                color_enabled(
+                    ColorMode::Auto,
+                    env::var_os("NO_COLOR"),
+                    env::var_os("CLICOLOR_FORCE"),
+                    io::stdout().is_terminal(),
+                )
+            }),
+            mode => color_enabled(mode, None, None, false),
+        }
+    }
+
+    /// `max_columns` with resolved terminal width
+    pub fn column_limit(&self) -> Option<usize> {
+        static TERMINAL_WIDTH: OnceLock<Option<usize>> = OnceLock::new();
+
+        match self.max_columns? {
+            MaxColumns::Fixed(columns) => Some(columns),
+            MaxColumns::Auto => *TERMINAL_WIDTH
+                .get_or_init(|| terminal_size().map(|(Width(width), _)| usize::from(width))),
+        }
+    }
+}
+
+/// CLICOLOR_FORCE takes priority over NO_COLOR, both over terminal check
+pub fn color_enabled(
+    mode: ColorMode,
+    no_color: Option<OsString>,
+    force: Option<OsString>,
+    is_terminal: bool,
+) -> bool {
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            if force.is_some_and(|force| force != "0") {
+                return true;
+            }
+
+            if no_color.is_some_and(|no_color| !no_color.is_empty()) {
+                return false;
+            }
+
+            is_terminal
+        }
+    }
+}
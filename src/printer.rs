@@ -1,19 +1,142 @@
-use std::cmp::{max, min};
+use std::{
+    borrow::Cow,
+    cmp::{max, min},
+    ops::Range,
+};
 
-use colored::{ColoredString, Colorize};
+use unicode_segmentation::GraphemeCursor;
+use unicode_width::UnicodeWidthStr;
 
-use crate::arguments::{Color, ShowConfig};
+use crate::{
+    grep::{Match, Offsets, fold_case},
+    options::{Mode, Options, PrintOptions, WindowUnit},
+    searcher::non_overlapping,
+    style::{Palette, Role},
+};
 
-pub fn construct_line(
+/// Marker of trimmed part of line
+const ELLIPSIS: &str = "…";
+
+/// Placeholder of line omitted by `--max-columns`
+const OMITTED: &str = "[Omitted long line]";
+
+/// Where the printed line is in the file: its number and byte offset of its start
+#[derive(Debug, Clone, Copy)]
+pub struct LinePosition {
+    pub index: usize,
+    pub offset: usize,
+}
+
+/// Shows found lines as the binary prints them
+#[derive(Debug, Clone)]
+pub struct Printer {
+    config: PrintOptions,
+    mode: Mode,
+    records: bool,
+    ignore_case: bool,
+    show_path: bool,
+}
+
+impl Printer {
+    pub fn new(config: PrintOptions, options: &Options) -> Self {
+        // Like grep, prefix lines with file name when several files are searched
+        let show_path = options.paths.len() > 1 || options.walk.recursive || config.vimgrep;
+
+        Printer {
+            config,
+            mode: options.mode,
+            records: options.record_mode(),
+            ignore_case: options.ignore_case,
+            show_path,
+        }
+    }
+
+    /// Printed lines of the found one. Vimgrep shows every match as a separate line
+    pub fn lines(&self, found: &Match) -> Vec<String> {
+        let config = &self.config;
+        let position = LinePosition {
+            index: found.line_number - 1,
+            offset: found.byte_offset,
+        };
+
+        // Lines are shown as they were searched, prefix has offsets of the original line
+        let (line, offsets) = if self.ignore_case {
+            let (folded, offsets) = fold_case(&found.line);
+            (Cow::Owned(folded), offsets)
+        } else {
+            (Cow::Borrowed(found.line.as_str()), Offsets::default())
+        };
+        let spans: Vec<_> = found
+            .spans
+            .iter()
+            .map(|&(l, r)| (offsets.folded(l, false), offsets.folded(r, true)))
+            .collect();
+        let line = line.as_ref();
+
+        let lines = if spans.is_empty() {
+            vec![construct_reverse_line(String::from(line), position, config)]
+        } else if config.vimgrep {
+            // Vimgrep needs the whole line instead of window around match
+            spans
+                .iter()
+                .map(|&span| construct_line_all(line, position, vec![span], None, &offsets, config))
+                .collect()
+        } else if matches!(self.mode, Mode::All) || self.records {
+            // Records are shown whole in every mode to keep the entry a match belongs to
+            let window_size = (!self.records).then_some(config.window_size);
+            vec![construct_line_all(
+                line,
+                position,
+                spans,
+                window_size,
+                &offsets,
+                config,
+            )]
+        } else {
+            let (left, right) = spans[0];
+            vec![construct_line(
+                left,
+                right,
+                (position, String::from(line)),
+                &offsets,
+                config,
+            )]
+        };
+
+        if !self.show_path {
+            return lines;
+        }
+
+        let path = field(Role::Path, &found.path, config);
+        lines
+            .into_iter()
+            .map(|line| format!("{path}{line}"))
+            .collect()
+    }
+}
+
+fn construct_line(
     left: usize,
     right: usize,
-    pair: (usize, String),
-    show_config: &ShowConfig,
+    pair: (LinePosition, String),
+    offsets: &Offsets,
+    show_config: &PrintOptions,
 ) -> String {
     let edge_size = show_config.window_size;
+    let unit = show_config.window_unit;
+
+    let mut start = window_start(&pair.1, left, edge_size, unit);
+    let mut end = window_end(&pair.1, right, edge_size, unit);
 
-    let start = max(0, left.saturating_sub(edge_size));
-    let end = min(pair.1.len(), right + edge_size);
+    if let Some(limit) = exceeded_limit(&pair.1[start..end], show_config) {
+        if !show_config.max_columns_preview {
+            return omitted(pair.0, offsets.original(left, false), show_config);
+        }
+
+        let side = limit.saturating_sub(pair.1[left..right].width()) / 2;
+        start = window_start(&pair.1, left, side, WindowUnit::Columns);
+        end = window_end(&pair.1, right, side, WindowUnit::Columns);
+    }
 
     let substring = &pair.1[start..end];
 
@@ -21,90 +144,276 @@ pub fn construct_line(
     let pattern_end = right - start;
 
     let left_part = &substring[..pattern_start];
-    let pattern = &substring[pattern_start..pattern_end];
+    let pattern = show_config
+        .replace
+        .as_deref()
+        .unwrap_or(&substring[pattern_start..pattern_end]);
     let right_part = &substring[pattern_end..];
 
-    let mut res = String::new();
+    let mut res = prefix(show_config, pair.0, offsets.original(left, false));
 
-    if show_config.number {
-        res = res + &format!("{}:", pair.0 + 1);
+    if start > 0 {
+        res = res + &paint(Role::Separator, ELLIPSIS, show_config);
     }
 
     res = res
         + &format!(
             "{}{}{}",
-            left_part,
-            take_color(pattern, show_config.color),
-            right_part
+            paint(Role::SelectedLine, left_part, show_config),
+            paint(Role::Match, pattern, show_config),
+            paint(Role::SelectedLine, right_part, show_config)
         );
 
+    if end < pair.1.len() {
+        res = res + &paint(Role::Separator, ELLIPSIS, show_config);
+    }
+
     res
 }
 
-pub fn construct_reverse_line(line: String, line_ind: usize, show_config: &ShowConfig) -> String {
-    let funcs = get_update_functions(show_config, line_ind);
+fn construct_reverse_line(
+    line: String,
+    position: LinePosition,
+    show_config: &PrintOptions,
+) -> String {
+    let Some(limit) = exceeded_limit(&line, show_config) else {
+        return prefix(show_config, position, 0) + &paint(Role::SelectedLine, &line, show_config);
+    };
+
+    if !show_config.max_columns_preview {
+        return omitted(position, 0, show_config);
+    }
 
-    update_string(line, funcs)
+    let end = expand(&line, 0, limit, WindowUnit::Columns, next_grapheme);
+    prefix(show_config, position, 0)
+        + &paint(Role::SelectedLine, &line[..end], show_config)
+        + &paint(Role::Separator, ELLIPSIS, show_config)
 }
 
-pub fn construct_line_all(
+/// Every match with its window, overlapping windows are merged and gaps are marked.
+/// Without window the whole line is shown
+fn construct_line_all(
     s: &str,
-    line_ind: usize,
-    pattern: &str,
+    position: LinePosition,
     vec: Vec<(usize, usize)>,
-    show_config: &ShowConfig,
+    window_size: Option<usize>,
+    offsets: &Offsets,
+    show_config: &PrintOptions,
 ) -> String {
-    let result = String::with_capacity(s.len());
+    // Preview shows exactly what in-place editing writes
+    let vec = if show_config.replace.is_some() {
+        non_overlapping(vec)
+    } else {
+        vec
+    };
 
-    let func = get_update_functions(show_config, line_ind);
-    let mut result = update_string(result, func);
-    let chars: Vec<char> = s.chars().collect();
+    let first_match = offsets.original(vec.first().map_or(0, |pair| pair.0), false);
+    let mut result = prefix(show_config, position, first_match);
 
+    let (size, unit) = match window_size {
+        Some(size) => (size, show_config.window_unit),
+        None => (usize::MAX, WindowUnit::Bytes),
+    };
+
+    let mut windows = windows(s, &vec, size, unit);
+
+    let shown: usize = windows.iter().map(|window| s[window.clone()].width()).sum();
+    if let Some(limit) = show_config.column_limit().filter(|&limit| limit < shown) {
+        if !show_config.max_columns_preview {
+            return omitted(position, first_match, show_config);
+        }
+
+        let side = limit.saturating_sub(vec.first().map_or(0, |&(l, r)| s[l..r].width())) / 2;
+        windows = self::windows(s, &vec[..1], side, WindowUnit::Columns);
+    }
+
+    let mut matches = vec.into_iter().peekable();
     let mut i = 0;
-    for pair in vec {
-        while i < pair.0 {
-            result.push(chars[i]);
-            i += 1;
+    for window in windows {
+        if i < window.start {
+            result.push_str(&paint(Role::Separator, ELLIPSIS, show_config));
         }
+        i = window.start;
+
+        while let Some(pair) = matches.next_if(|pair| pair.0 < window.end) {
+            if i < pair.0 {
+                result.push_str(&paint(Role::SelectedLine, &s[i..pair.0], show_config));
+            }
 
-        result = result + &format!("{}", take_color(pattern, show_config.color));
+            // Found part may differ from the pattern, e.g. in fuzzy search
+            let pattern = show_config.replace.as_deref().unwrap_or(&s[pair.0..pair.1]);
+            result = result + &paint(Role::Match, pattern, show_config);
 
-        i = pair.1;
+            i = pair.1;
+        }
+
+        if i < window.end {
+            result.push_str(&paint(Role::SelectedLine, &s[i..window.end], show_config));
+        }
+        i = max(i, window.end);
     }
 
-    while i < chars.len() {
-        result.push(chars[i]);
-        i += 1;
+    if i < s.len() {
+        result.push_str(&paint(Role::Separator, ELLIPSIS, show_config));
     }
 
     result
 }
 
-// I hate myself...
-fn get_update_functions(
-    config: &ShowConfig,
-    line_ind: usize,
-) -> Vec<Box<dyn Fn(String) -> String>> {
-    let mut funcs: Vec<Box<dyn Fn(String) -> String>> = Vec::new();
+/// Windows of `size` units around matches, merged when they overlap or touch
+fn windows(
+    s: &str,
+    matches: &[(usize, usize)],
+    size: usize,
+    unit: WindowUnit,
+) -> Vec<Range<usize>> {
+    let mut result: Vec<Range<usize>> = Vec::new();
+
+    for &(left, right) in matches {
+        let start = window_start(s, left, size, unit);
+        let end = window_end(s, right, size, unit);
 
-    if config.number {
-        funcs.push(Box::new(move |x: String| format!("{}:", line_ind + 1) + &x));
+        match result.last_mut() {
+            Some(last) if start <= last.end => last.end = max(last.end, end),
+            _ => result.push(start..end),
+        }
     }
 
-    funcs
+    result
 }
 
-fn update_string<F>(src: String, funcs: Vec<F>) -> String
+/// Limit of `--max-columns` when the shown text is wider
+fn exceeded_limit(shown: &str, config: &PrintOptions) -> Option<usize> {
+    // Width is never more than length, so short text is not measured
+    config
+        .column_limit()
+        .filter(|&limit| limit < shown.len() && limit < shown.width())
+}
+
+/// Placeholder of long line
+fn omitted(position: LinePosition, first_match: usize, config: &PrintOptions) -> String {
+    prefix(config, position, first_match) + &paint(Role::Separator, OMITTED, config)
+}
+
+/// Enabled parts of `line:column:offset:` prefix. Column and offset are of the first shown match.
+fn prefix(config: &PrintOptions, position: LinePosition, first_match: usize) -> String {
+    let mut res = String::new();
+
+    if config.number || config.vimgrep {
+        res = res + &field(Role::LineNumber, position.index + 1, config);
+    }
+
+    if config.column || config.vimgrep {
+        res = res + &field(Role::Column, first_match + 1, config);
+    }
+
+    if config.byte_offset {
+        res = res + &field(Role::ByteOffset, position.offset + first_match, config);
+    }
+
+    res
+}
+
+fn field(role: Role, value: impl ToString, config: &PrintOptions) -> String {
+    paint(role, &value.to_string(), config) + &paint(Role::Separator, ":", config)
+}
+
+/// Start of window before `left`. Byte window is widened to whole graphemes,
+/// other units take only graphemes fitting in the window
+fn window_start(s: &str, left: usize, size: usize, unit: WindowUnit) -> usize {
+    match unit {
+        WindowUnit::Bytes => floor_grapheme(s, left.saturating_sub(size)),
+        _ => expand(s, floor_grapheme(s, left), size, unit, prev_grapheme),
+    }
+}
+
+fn window_end(s: &str, right: usize, size: usize, unit: WindowUnit) -> usize {
+    match unit {
+        WindowUnit::Bytes => ceil_grapheme(s, min(s.len(), right.saturating_add(size))),
+        _ => expand(s, ceil_grapheme(s, right), size, unit, next_grapheme),
+    }
+}
+
+/// Step by graphemes from `ind` while they fit in `size` units
+fn expand<F>(s: &str, mut ind: usize, size: usize, unit: WindowUnit, step: F) -> usize
 where
-    F: Fn(String) -> String,
+    F: Fn(&str, usize) -> Option<usize>,
 {
-    funcs.iter().fold(src, |src, f| f(src))
+    let mut used = 0usize;
+
+    while let Some(next) = step(s, ind) {
+        let grapheme = &s[min(ind, next)..max(ind, next)];
+        let cost = match unit {
+            WindowUnit::Columns => grapheme.width(),
+            _ => 1,
+        };
+
+        if used.saturating_add(cost) > size {
+            break;
+        }
+
+        used += cost;
+        ind = next;
+    }
+
+    ind
+}
+
+fn prev_grapheme(s: &str, ind: usize) -> Option<usize> {
+    GraphemeCursor::new(ind, s.len(), true)
+        .prev_boundary(s, 0)
+        .ok()
+        .flatten()
+}
+
+fn next_grapheme(s: &str, ind: usize) -> Option<usize> {
+    GraphemeCursor::new(ind, s.len(), true)
+        .next_boundary(s, 0)
+        .ok()
+        .flatten()
 }
 
-fn take_color(source: &str, color: Color) -> ColoredString {
-    match color {
-        Color::Red => source.red(),
-        Color::Green => source.green(),
-        Color::Blue => source.blue(),
+fn floor_grapheme(s: &str, ind: usize) -> usize {
+    let ind = s.floor_char_boundary(ind);
+
+    if GraphemeCursor::new(ind, s.len(), true).is_boundary(s, 0) == Ok(true) {
+        ind
+    } else {
+        prev_grapheme(s, ind).unwrap_or(0)
+    }
+}
+
+fn ceil_grapheme(s: &str, ind: usize) -> usize {
+    let ind = s.ceil_char_boundary(ind);
+
+    if GraphemeCursor::new(ind, s.len(), true).is_boundary(s, 0) == Ok(true) {
+        ind
+    } else {
+        next_grapheme(s, ind).unwrap_or(s.len())
     }
 }
+
+/// Style of the role: `--match-color` for matches, then `--colors`, environment and defaults
+fn paint(role: Role, source: &str, show_config: &PrintOptions) -> String {
+    if !show_config.use_color() {
+        return String::from(source);
+    }
+
+    let key = role.key();
+    let explicit = if role == Role::Match {
+        show_config.color.as_ref()
+    } else {
+        None
+    };
+
+    explicit
+        .or_else(|| {
+            show_config
+                .colors
+                .as_ref()
+                .and_then(|palette| palette.get(key))
+        })
+        .or_else(|| Palette::from_env().get(key))
+        .or_else(|| Palette::defaults().get(key))
+        .map_or_else(|| String::from(source), |style| style.paint(source))
+}
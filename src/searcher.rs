@@ -2,7 +2,7 @@ pub type SearchResult = Option<(usize, usize)>;
 pub type SearchResults = Option<Vec<(usize, usize)>>;
 pub type ReverseResult = bool;
 
-pub trait Searcher {
+pub trait Searcher: Send + Sync {
     fn search_left(&self, pattern: &str, source: &str) -> SearchResult;
 
     fn search_right(&self, pattern: &str, source: &str) -> SearchResult;
@@ -11,3 +11,17 @@ pub trait Searcher {
 
     fn reverse(&self, pattern: &str, source: &str) -> ReverseResult;
 }
+
+/// Drop spans overlapping an earlier kept one, as replacement can't apply to both.
+/// Shared by replace preview and in-place editing, so both change the same parts.
+pub fn non_overlapping(mut spans: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    let mut end = 0;
+    spans.retain(|&(l, r)| {
+        let keep = l >= end;
+        if keep {
+            end = r;
+        }
+        keep
+    });
+    spans
+}
@@ -0,0 +1,261 @@
+use std::{collections::HashMap, env, sync::OnceLock};
+
+/// Look of a printed element as parameters of ANSI SGR escape, e.g. `1;38;5;208`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    sgr: String,
+}
+
+impl Style {
+    /// Parse comma separated list of: color for text (name, 0-255, #rrggbb),
+    /// `bg=COLOR` for background, `bold`, `dim`, `italic`, `underline`, `reverse`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut attributes = Vec::new();
+        let mut foreground = None;
+        let mut background = None;
+
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let token = token.to_lowercase();
+
+            match token.as_str() {
+                "bold" => attributes.push("1"),
+                "dim" => attributes.push("2"),
+                "italic" => attributes.push("3"),
+                "underline" => attributes.push("4"),
+                "reverse" => attributes.push("7"),
+                _ => match token.strip_prefix("bg=") {
+                    Some(color) => background = Some(color_code(color, true)?),
+                    None => {
+                        let color = token.strip_prefix("fg=").unwrap_or(&token);
+                        foreground = Some(color_code(color, false)?);
+                    }
+                },
+            }
+        }
+
+        let sgr = attributes
+            .into_iter()
+            .map(String::from)
+            .chain(foreground)
+            .chain(background)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        Ok(Style { sgr })
+    }
+
+    /// Raw SGR parameters as in `GREP_COLORS`, e.g. `01;31`
+    pub fn from_sgr(sgr: &str) -> Result<Self, String> {
+        if !sgr.chars().all(|c| c.is_ascii_digit() || c == ';') {
+            return Err(format!("'{sgr}' is not SGR sequence"));
+        }
+
+        Ok(Style {
+            sgr: String::from(sgr),
+        })
+    }
+
+    pub fn paint(&self, text: &str) -> String {
+        if self.sgr.is_empty() || text.is_empty() {
+            return String::from(text);
+        }
+
+        format!("\x1b[{}m{}\x1b[0m", self.sgr, text)
+    }
+}
+
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// SGR parameters of color for text or background
+fn color_code(color: &str, background: bool) -> Result<String, String> {
+    let base = if background { 40 } else { 30 };
+
+    if let Some(hex) = color.strip_prefix('#') {
+        let channel = |ind: usize| {
+            hex.get(ind..ind + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(format!("{};2;{r};{g};{b}", base + 8)),
+            _ => Err(format!("'{color}' is not #rrggbb color")),
+        };
+    }
+
+    if let Ok(index) = color.parse::<u8>() {
+        return Ok(format!("{};5;{index}", base + 8));
+    }
+
+    let (bright, name) = match color.strip_prefix("bright-") {
+        Some(name) => (true, name),
+        None => (false, color),
+    };
+
+    match NAMES.iter().position(|&n| n == name) {
+        Some(pos) if bright => Ok((base + 60 + pos).to_string()),
+        Some(pos) => Ok((base + pos).to_string()),
+        None => Err(format!("unknown color '{color}'")),
+    }
+}
+
+/// Printed element with its own style, named by key of `GREP_COLORS`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Path,
+    LineNumber,
+    Column,
+    ByteOffset,
+    Separator,
+    Match,
+    SelectedLine,
+}
+
+impl Role {
+    pub fn key(self) -> &'static str {
+        match self {
+            Role::Path => "fn",
+            Role::LineNumber => "ln",
+            Role::Column => "cn",
+            Role::ByteOffset => "bn",
+            Role::Separator => "se",
+            Role::Match => "ms",
+            Role::SelectedLine => "sl",
+        }
+    }
+}
+
+/// Styles of roles by their keys, e.g. `ms=01;31:ln=32`
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    styles: HashMap<String, Style>,
+}
+
+impl Palette {
+    /// Strict variant of `extend` with `Style::parse` values, e.g. `fn=magenta:ln=bold,green`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut palette = Palette::default();
+
+        for entry in spec.split(':').filter(|entry| !entry.is_empty()) {
+            let Some((key, value)) = entry.split_once('=') else {
+                return Err(format!("'{entry}' is not KEY=STYLE"));
+            };
+
+            palette.insert(key, Style::parse(value)?);
+        }
+
+        Ok(palette)
+    }
+
+    /// Same look as GNU grep
+    pub fn defaults() -> &'static Palette {
+        static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+        PALETTE.get_or_init(|| {
+            Palette::parse("mt=red:fn=magenta:ln=green:cn=green:bn=green:se=cyan")
+                .unwrap_or_default()
+        })
+    }
+
+    /// `GREP_RS_COLORS` (values as for `Style::parse`) over `GREP_COLORS` (values are SGR)
+    pub fn from_env() -> &'static Palette {
+        static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+        PALETTE.get_or_init(|| {
+            let mut palette = Palette::default();
+
+            if let Ok(spec) = env::var("GREP_COLORS") {
+                palette.extend(&spec, Style::from_sgr);
+            }
+
+            if let Ok(spec) = env::var("GREP_RS_COLORS") {
+                palette.extend(&spec, Style::parse);
+            }
+
+            palette
+        })
+    }
+
+    /// Invalid entries are skipped like in grep
+    pub fn extend<F>(&mut self, spec: &str, parse: F)
+    where
+        F: Fn(&str) -> Result<Style, String>,
+    {
+        for (key, value) in spec.split(':').filter_map(|entry| entry.split_once('=')) {
+            if let Ok(style) = parse(value) {
+                self.insert(key, style);
+            }
+        }
+    }
+
+    fn insert(&mut self, key: &str, style: Style) {
+        // `mt` sets both selected and context matches
+        if key == "mt" {
+            self.styles.insert(String::from("ms"), style.clone());
+            self.styles.insert(String::from("mc"), style);
+        } else {
+            self.styles.insert(String::from(key), style);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Style> {
+        self.styles.get(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named() {
+        assert_eq!(Style::parse("red").unwrap().sgr, "31");
+        assert_eq!(Style::parse("bright-blue").unwrap().sgr, "94");
+        assert_eq!(Style::parse("bg=green").unwrap().sgr, "42");
+    }
+
+    #[test]
+    fn indexed_and_true_color() {
+        assert_eq!(Style::parse("208").unwrap().sgr, "38;5;208");
+        assert_eq!(Style::parse("#ff8800").unwrap().sgr, "38;2;255;136;0");
+        assert_eq!(Style::parse("bg=#000000").unwrap().sgr, "48;2;0;0;0");
+    }
+
+    #[test]
+    fn styles() {
+        let style = Style::parse("underline, bold, fg=yellow, bg=17").unwrap();
+        assert_eq!(style.sgr, "4;1;33;48;5;17");
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Style::parse("purple").is_err());
+        assert!(Style::parse("#ff88").is_err());
+        assert!(Style::parse("256").is_err());
+        assert!(Style::from_sgr("red").is_err());
+    }
+
+    #[test]
+    fn grep_colors() {
+        let mut palette = Palette::default();
+        palette.extend("mt=01;31:ln=32:fn=:ne:xx=bad", Style::from_sgr);
+        palette.extend("ln=bold,cyan", Style::parse);
+
+        assert_eq!(palette.get("ms").unwrap().sgr, "01;31");
+        assert_eq!(palette.get("mc").unwrap().sgr, "01;31");
+        assert_eq!(palette.get("ln").unwrap().sgr, "1;36");
+        assert_eq!(palette.get("fn").unwrap().sgr, "");
+        assert!(palette.get("xx").is_none());
+    }
+
+    #[test]
+    fn command_line_palette() {
+        let palette = Palette::parse("fn=bold,magenta:se=bright-black").unwrap();
+        assert_eq!(palette.get("fn").unwrap().sgr, "1;35");
+        assert_eq!(palette.get("se").unwrap().sgr, "90");
+
+        assert!(Palette::parse("fn").is_err());
+        assert!(Palette::parse("ln=purple").is_err());
+    }
+}
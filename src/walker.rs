@@ -0,0 +1,116 @@
+use std::{iter, sync::Arc};
+
+use ignore::{
+    DirEntry, WalkBuilder,
+    overrides::{Override, OverrideBuilder},
+    types::{Types, TypesBuilder},
+};
+
+use crate::{
+    grep_error::{ErrorType, GrepResult},
+    options::WalkOptions,
+};
+
+/// Files for search in walk order, shared by search threads
+pub type Files<'a> = Box<dyn Iterator<Item = GrepResult<String>> + Send + 'a>;
+
+/// Expand directories from `paths` into files for search. Without recursive
+/// mode paths are searched as is. Like in ripgrep, globs and file types filter
+/// only walked files, not explicitly given ones. Files are found lazily,
+/// so search can stop before the whole tree is walked.
+pub fn walk<'a>(paths: &'a [String], config: &'a WalkOptions) -> Files<'a> {
+    // Invalid globs and types are reported even when nothing is walked
+    if let Err(err) = filters(".", config) {
+        return Box::new(iter::once(Err(ErrorType::InvalidArguments(
+            err.to_string(),
+        ))));
+    }
+
+    if !config.recursive {
+        return Box::new(paths.iter().cloned().map(Ok));
+    }
+
+    Box::new(paths.iter().flat_map(|path| walk_root(path, config)))
+}
+
+/// Globs are matched relative to the root they are walked from
+fn walk_root<'a>(root: &str, config: &WalkOptions) -> Files<'a> {
+    let (overrides, types) = match filters(root, config) {
+        Ok(filters) => filters,
+        Err(err) => {
+            return Box::new(iter::once(Err(ErrorType::InvalidArguments(
+                err.to_string(),
+            ))));
+        }
+    };
+
+    let mut builder = WalkBuilder::new(root);
+
+    // Only git ignore rules and .grepignore, not .ignore files
+    builder
+        .standard_filters(!config.no_ignore)
+        .ignore(false)
+        .hidden(!config.hidden)
+        .max_depth(config.max_depth)
+        .follow_links(config.follow)
+        .same_file_system(config.one_file_system)
+        .overrides(overrides)
+        .types(types)
+        .sort_by_file_name(|a, b| a.cmp(b));
+
+    if !config.no_ignore {
+        builder.add_custom_ignore_filename(".grepignore");
+    }
+
+    Box::new(builder.build().filter_map(|entry| match entry {
+        Ok(entry) => is_searchable(&entry).then(|| Ok(entry.path().to_string_lossy().into_owned())),
+        // Unreadable entries and symlink loops are reported, the walk goes on
+        Err(err) => Some(Err(walk_error(err))),
+    }))
+}
+
+/// Path of failed entry is taken out of the error
+fn walk_error(err: ignore::Error) -> ErrorType {
+    match err {
+        ignore::Error::WithDepth { err, .. } => walk_error(*err),
+        ignore::Error::WithPath { path, err } => ErrorType::Open {
+            path: path.to_string_lossy().into_owned(),
+            source: Arc::new(*err),
+        },
+        ignore::Error::Loop { ref child, .. } => ErrorType::Open {
+            path: child.to_string_lossy().into_owned(),
+            source: Arc::new(err),
+        },
+        err => ErrorType::Open {
+            path: String::new(),
+            source: Arc::new(err),
+        },
+    }
+}
+
+/// Found FIFOs, sockets and devices are skipped, so search never blocks on them.
+/// Explicitly given paths are searched whatever they are, except directories.
+fn is_searchable(entry: &DirEntry) -> bool {
+    entry
+        .file_type()
+        .is_some_and(|file_type| file_type.is_file() || (entry.depth() == 0 && !file_type.is_dir()))
+}
+
+/// Glob and file type filters, built before enumeration of paths under `root`
+fn filters(root: &str, config: &WalkOptions) -> Result<(Override, Types), ignore::Error> {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in &config.globs {
+        overrides.add(glob)?;
+    }
+
+    let mut types = TypesBuilder::new();
+    types.add_defaults();
+    for def in &config.type_add {
+        types.add_def(def)?;
+    }
+    for name in &config.types {
+        types.select(name);
+    }
+
+    Ok((overrides.build()?, types.build()?))
+}